@@ -0,0 +1,376 @@
+// Windows backend: one borderless, click-through, always-on-top layered window per monitor,
+// filled with the tint color and blended with SetLayeredWindowAttributes.
+//
+// The event loop sleeps while the tint is constant. The overlay wakes it with a user event when
+// something changes, and it ticks at FRAME_INTERVAL only during fades. Once a second it also
+// re-checks the monitor list, since winit has no event for monitors coming and going, and puts
+// the windows back on top, except over a fullscreen window the overlay is configured to make
+// way for.
+//
+// All geometry is in physical pixels. winit reports monitors that way, and the process is per-monitor
+// DPI aware, so a window placed with physical coordinates covers exactly one monitor whatever its
+// scale factor. Logical units would be scaled by the scale factor of whichever monitor the window
+// ends up on. The placement rules themselves live in placement.rs.
+
+use pixels::{Pixels, SurfaceTexture};
+use std::collections::HashMap;
+use std::time::Instant;
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::{Event, StartCause, WindowEvent},
+    event_loop::{ControlFlow, EventLoop, EventLoopWindowTarget},
+    monitor::MonitorHandle,
+    window::{Window, WindowBuilder, WindowId},
+};
+use winit::platform::windows::{WindowBuilderExtWindows, WindowExtWindows};
+
+use winapi::{
+    shared::windef::{HWND, RECT},
+    um::processthreadsapi::GetCurrentProcessId,
+    um::winuser::{
+        GetWindowLongW, SetWindowLongW, SetWindowPos, GWL_EXSTYLE, SWP_NOMOVE, SWP_NOSIZE,
+        SWP_NOACTIVATE, WS_EX_LAYERED, WS_EX_TRANSPARENT, WS_EX_TOOLWINDOW, WS_EX_TOPMOST,
+        WS_EX_NOACTIVATE, SetLayeredWindowAttributes, LWA_ALPHA, HWND_TOPMOST,
+        GetClassNameW, GetForegroundWindow, GetMonitorInfoW, GetWindowRect, GetWindowThreadProcessId,
+        MonitorFromWindow, MONITORINFO, MONITORINFOEXW, MONITOR_DEFAULTTONULL,
+    },
+};
+
+use super::placement::{self, Action, Monitor, Placement};
+use super::{BackendError, Output, OutputId, TintBackend, FRAME_INTERVAL, HOUSEKEEPING_INTERVAL};
+use crate::color::Tint;
+use crate::overlay::{self, Frame, TintDriver};
+
+struct OverlayWindow {
+    window: Window,
+    pixels: Pixels,
+    output: Output,
+    // What the frame buffer currently holds
+    tint: Option<Tint>,
+}
+
+pub struct LayeredBackend {
+    windows: HashMap<WindowId, OverlayWindow>,
+    // As of the last sync_monitors
+    monitors: Vec<Monitor>,
+}
+
+impl LayeredBackend {
+    pub fn new(event_loop: &EventLoop<()>, driver: &TintDriver) -> Self {
+        let mut backend = Self { windows: HashMap::new(), monitors: Vec::new() };
+        backend.sync_monitors(event_loop, driver);
+        if backend.windows.is_empty() {
+            eprintln!("No monitor matched the overlay's monitor selection");
+        }
+        backend
+    }
+
+    // Bring the windows in line with the connected monitors: new monitors get a window, windows
+    // of unplugged monitors are closed and moved or resized monitors get their window adjusted.
+    fn sync_monitors(&mut self, target: &EventLoopWindowTarget<()>, driver: &TintDriver) {
+        self.monitors = target
+            .available_monitors()
+            .map(|monitor| monitor_info(&monitor))
+            .filter(|monitor| driver.shows_on(&monitor.name))
+            .collect();
+        for action in placement::plan(&self.outputs(), &self.monitors) {
+            match action {
+                Action::Create(monitor) => {
+                    println!("Monitor {} added, creating overlay", monitor.name);
+                    self.create_overlay_window(target, &monitor);
+                }
+                Action::Move(id, Placement { position, size }) => {
+                    let Some(overlay) = self.windows.get_mut(&WindowId::from(id)) else { continue };
+                    println!(
+                        "Monitor {} changed to {}x{} at {},{}",
+                        overlay.output.name, size.0, size.1, position.0, position.1
+                    );
+                    overlay.output.position = position;
+                    overlay.output.size = size;
+                    overlay.window.set_outer_position(PhysicalPosition::new(position.0, position.1));
+                    overlay.window.set_inner_size(PhysicalSize::new(size.0, size.1));
+                    // Repaint at the new size
+                    overlay.tint = None;
+                }
+                Action::Close(id) => {
+                    if let Some(overlay) = self.windows.remove(&WindowId::from(id)) {
+                        println!("Monitor {} was removed, closing its overlay", overlay.output.name);
+                    }
+                }
+            }
+        }
+    }
+
+    fn keep_on_top(&self, driver: &TintDriver) {
+        for overlay in self.windows.values() {
+            if driver.yields_to_fullscreen(overlay.output.id) {
+                continue;
+            }
+            unsafe {
+                SetWindowPos(
+                    overlay.window.hwnd() as HWND,
+                    HWND_TOPMOST,
+                    0,
+                    0,
+                    0,
+                    0,
+                    SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+                );
+            }
+        }
+    }
+
+    fn scale_factor_changed(&self, window_id: WindowId, new_inner_size: &mut PhysicalSize<u32>) {
+        if let Some(overlay) = self.windows.get(&window_id) {
+            let (width, height) = placement::size_after_scale_change(&self.monitors, &overlay.output);
+            *new_inner_size = PhysicalSize::new(width, height);
+        }
+    }
+
+    fn resize(&mut self, window_id: WindowId, width: u32, height: u32) {
+        if let Some(overlay) = self.windows.get_mut(&window_id) {
+            overlay.pixels.resize_surface(width, height).unwrap_or_else(|e| {
+                eprintln!("Failed to resize surface: {}", e);
+            });
+            overlay.window.request_redraw();
+        }
+    }
+
+    // Present the frame buffer again, e.g. after the window was resized or uncovered
+    fn redraw(&mut self, window_id: WindowId) {
+        if let Some(overlay) = self.windows.get_mut(&window_id) {
+            if let Err(e) = overlay.pixels.render() {
+                eprintln!("Failed to render pixels: {}", e);
+            }
+        }
+    }
+
+    fn create_overlay_window(&mut self, event_loop: &EventLoopWindowTarget<()>, monitor: &Monitor) {
+        let Placement { position, size } = Placement::of(monitor);
+        println!(
+            "Covering monitor {}: {}x{} at {},{} (scale {})",
+            monitor.name, size.0, size.1, position.0, position.1, monitor.scale_factor
+        );
+
+        let window = WindowBuilder::new()
+            .with_title("Red Overlay")
+            .with_inner_size(PhysicalSize::new(size.0, size.1))
+            .with_position(PhysicalPosition::new(position.0, position.1))
+            .with_decorations(false)
+            .with_transparent(true)
+            .with_skip_taskbar(true)
+            .build(event_loop)
+            .expect("Failed to build window");
+
+        let hwnd = window.hwnd() as HWND;
+        let ex_style = unsafe { GetWindowLongW(hwnd, GWL_EXSTYLE) } as u32;
+        let new_ex_style = ex_style
+            | WS_EX_LAYERED
+            | WS_EX_TRANSPARENT
+            | WS_EX_TOOLWINDOW
+            | WS_EX_TOPMOST
+            | WS_EX_NOACTIVATE;
+
+        unsafe {
+            SetWindowLongW(hwnd, GWL_EXSTYLE, new_ex_style as i32);
+            SetLayeredWindowAttributes(hwnd, 0, 0, LWA_ALPHA);
+            
+            // Keep window on top
+            SetWindowPos(
+                hwnd,
+                HWND_TOPMOST,
+                0,
+                0,
+                0,
+                0,
+                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
+            );
+        }
+
+        let window_size = window.inner_size();
+        let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
+        let pixels = Pixels::new(
+            window_size.width,
+            window_size.height,
+            surface_texture,
+        )
+        .expect("Failed to create Pixels");
+
+        let output = Output {
+            id: u64::from(window.id()),
+            name: monitor.name.clone(),
+            position,
+            size,
+        };
+        self.windows.insert(window.id(), OverlayWindow { window, pixels, output, tint: None });
+    }
+}
+
+impl TintBackend for LayeredBackend {
+    fn name(&self) -> &'static str {
+        "layered-window"
+    }
+
+    fn outputs(&self) -> Vec<Output> {
+        self.windows.values().map(|overlay| overlay.output.clone()).collect()
+    }
+
+    fn apply(&mut self, output: OutputId, tint: Tint) -> Result<(), BackendError> {
+        let overlay = self.windows
+            .get_mut(&WindowId::from(output))
+            .ok_or_else(|| BackendError(format!("no overlay window for output {}", output)))?;
+        // Nothing to do for an unchanged tint
+        if overlay.tint == Some(tint) {
+            return Ok(());
+        }
+        // Tint and dimming go into the same layer
+        let ([red, green, blue], alpha) = tint.layer();
+
+        let frame = overlay.pixels.frame_mut();
+        for pixel in frame.chunks_exact_mut(4) {
+            pixel.copy_from_slice(&[red, green, blue, alpha]);
+        }
+        overlay.pixels.render().map_err(|e| BackendError(format!("failed to render pixels: {}", e)))?;
+
+        // keep_on_top in the event loop takes care of the z-order
+        unsafe {
+            SetLayeredWindowAttributes(overlay.window.hwnd() as HWND, 0, alpha, LWA_ALPHA);
+        }
+        overlay.tint = Some(tint);
+        Ok(())
+    }
+
+    fn release(&mut self) {
+        self.windows.clear();
+    }
+
+    // Only the foreground window counts, so this is at most one monitor
+    fn fullscreen_outputs(&self) -> Vec<OutputId> {
+        let Some(monitor) = fullscreen_monitor() else { return Vec::new() };
+        self.windows
+            .values()
+            .filter(|overlay| overlay.output.name == monitor)
+            .map(|overlay| overlay.output.id)
+            .collect()
+    }
+}
+
+// Name of the monitor the foreground window fills completely, if it does. Maximized windows
+// leave the taskbar free, so this only catches borderless and exclusive fullscreen.
+fn fullscreen_monitor() -> Option<String> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return None;
+        }
+
+        // Not our own windows, and not the desktop, which covers the screen too
+        let mut process_id = 0;
+        GetWindowThreadProcessId(hwnd, &mut process_id);
+        if process_id == GetCurrentProcessId() {
+            return None;
+        }
+        let mut class = [0u16; 64];
+        let length = GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32);
+        let class = String::from_utf16_lossy(&class[..length.max(0) as usize]);
+        if matches!(class.as_str(), "Progman" | "WorkerW" | "Shell_TrayWnd") {
+            return None;
+        }
+
+        let mut window: RECT = std::mem::zeroed();
+        if GetWindowRect(hwnd, &mut window) == 0 {
+            return None;
+        }
+        let monitor = MonitorFromWindow(hwnd, MONITOR_DEFAULTTONULL);
+        if monitor.is_null() {
+            return None;
+        }
+        let mut info: MONITORINFOEXW = std::mem::zeroed();
+        info.cbSize = std::mem::size_of::<MONITORINFOEXW>() as u32;
+        if GetMonitorInfoW(monitor, &mut info as *mut MONITORINFOEXW as *mut MONITORINFO) == 0 {
+            return None;
+        }
+
+        let screen = info.rcMonitor;
+        let covers = window.left <= screen.left
+            && window.top <= screen.top
+            && window.right >= screen.right
+            && window.bottom >= screen.bottom;
+        if !covers {
+            return None;
+        }
+        let name_length = info.szDevice.iter().position(|&c| c == 0).unwrap_or(info.szDevice.len());
+        Some(String::from_utf16_lossy(&info.szDevice[..name_length]))
+    }
+}
+
+// Owns the winit event loop for the rest of the process
+pub fn run(mut driver: TintDriver) {
+    let event_loop = EventLoop::new();
+    let mut backend = LayeredBackend::new(&event_loop, &driver);
+
+    let proxy = event_loop.create_proxy();
+    overlay::set_waker(move || {
+        // Fails only once the event loop is gone
+        let _ = proxy.send_event(());
+    });
+
+    let mut next_housekeeping = Instant::now() + HOUSEKEEPING_INTERVAL;
+
+    event_loop.run(move |event, target, control_flow| {
+        match event {
+            Event::WindowEvent { event, window_id, .. } => match event {
+                WindowEvent::CloseRequested => {
+                    backend.release();
+                    *control_flow = ControlFlow::Exit;
+                }
+                WindowEvent::ScaleFactorChanged { new_inner_size, .. } => {
+                    backend.scale_factor_changed(window_id, new_inner_size)
+                }
+                WindowEvent::Resized(size) => backend.resize(window_id, size.width, size.height),
+                _ => (),
+            },
+            Event::RedrawRequested(window_id) => backend.redraw(window_id),
+            // Woken up by the overlay, or a fade step or housekeeping is due
+            Event::UserEvent(()) | Event::NewEvents(StartCause::ResumeTimeReached { .. }) | Event::NewEvents(StartCause::Init) => {
+                let now = Instant::now();
+                if now >= next_housekeeping {
+                    backend.sync_monitors(target, &driver);
+                    driver.set_fullscreen(backend.fullscreen_outputs());
+                    backend.keep_on_top(&driver);
+                    next_housekeeping = now + HOUSEKEEPING_INTERVAL;
+                }
+
+                match driver.frame(now, &backend.outputs()) {
+                    Frame::Paint(tints) => {
+                        // New windows start out unpainted, so this also covers freshly plugged monitors
+                        backend.apply_each(&tints);
+                        let next_frame = if driver.is_animating(now) {
+                            (now + FRAME_INTERVAL).min(next_housekeeping)
+                        } else {
+                            next_housekeeping
+                        };
+                        *control_flow = ControlFlow::WaitUntil(next_frame);
+                    }
+                    Frame::Exit => {
+                        backend.release();
+                        *control_flow = ControlFlow::Exit;
+                    }
+                }
+            }
+            _ => (),
+        }
+    });
+}
+
+// What placement.rs needs to know about a monitor, in physical pixels and desktop coordinates
+fn monitor_info(monitor: &MonitorHandle) -> Monitor {
+    let position = monitor.position();
+    let size = monitor.size();
+    Monitor {
+        name: monitor.name().unwrap_or_default(),
+        position: (position.x, position.y),
+        size: (size.width, size.height),
+        scale_factor: monitor.scale_factor(),
+    }
+}
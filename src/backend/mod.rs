@@ -0,0 +1,215 @@
+// Tint backends: the different ways of getting a tint onto the screen.
+//
+// The overlay process decides *what* tint to show (config, schedule, fades) and hands it to a
+// backend, which knows *how* to show it on each output. Backends are gated on the platform
+// they need.
+
+use std::collections::HashMap;
+use std::sync::mpsc;
+use serde::{Serialize, Deserialize};
+use std::time::{Duration, Instant};
+use crate::color::Tint;
+use crate::overlay::{self, Frame, TintDriver};
+
+#[cfg(target_os = "windows")]
+pub mod layered;
+#[cfg(any(target_os = "windows", test))]
+pub mod placement;
+#[cfg(target_os = "linux")]
+pub mod wayland;
+#[cfg(target_os = "linux")]
+pub mod x11;
+
+// Frame pacing during fades; while the tint is constant backends sleep until woken
+pub const FRAME_INTERVAL: Duration = Duration::from_millis(16);
+// How often backends look for added, removed or changed outputs
+pub const HOUSEKEEPING_INTERVAL: Duration = Duration::from_secs(1);
+
+// Backend-specific handle for one output (a window id, a CRTC, ...)
+pub type OutputId = u64;
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct Output {
+    pub id: OutputId,
+    pub name: String,
+    // Physical pixels, in the desktop's coordinate space
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+}
+
+#[derive(Debug)]
+pub struct BackendError(pub String);
+
+impl std::fmt::Display for BackendError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for BackendError {}
+
+pub trait TintBackend {
+    fn name(&self) -> &'static str;
+
+    // Everything the backend can currently tint
+    fn outputs(&self) -> Vec<Output>;
+
+    fn apply(&mut self, output: OutputId, tint: Tint) -> Result<(), BackendError>;
+
+    // Undo whatever the backend did to the screen (close windows, restore gamma ramps)
+    fn release(&mut self);
+
+    // Pick up outputs that were plugged in, unplugged or reconfigured since the last call.
+    // Returns whether the output list changed.
+    fn refresh_outputs(&mut self) -> Result<bool, BackendError> {
+        Ok(false)
+    }
+
+    // Outputs that a fullscreen window (a game, a video player) currently fills
+    fn fullscreen_outputs(&self) -> Vec<OutputId> {
+        Vec::new()
+    }
+
+    fn apply_each(&mut self, tints: &[(OutputId, Tint)]) {
+        for &(output, tint) in tints {
+            if let Err(e) = self.apply(output, tint) {
+                eprintln!("{}: failed to tint output {}: {}", self.name(), output, e);
+            }
+        }
+    }
+}
+
+// Scale a gamma ramp by `gain`, keeping whatever calibration it already had
+pub fn scale_ramp(ramp: &[u16], gain: f32) -> Vec<u16> {
+    let gain = gain.clamp(0.0, 1.0);
+    ramp.iter().map(|&value| (value as f32 * gain).round() as u16).collect()
+}
+
+// Linear ramp for backends that can't read back the current one
+pub fn identity_ramp(size: usize) -> Vec<u16> {
+    let last = size.saturating_sub(1).max(1) as f32;
+    (0..size).map(|i| (i as f32 / last * u16::MAX as f32).round() as u16).collect()
+}
+
+// Frame loop for backends that don't bring their own event loop. Only talks to the
+// backend when an output's tint actually changes, and sleeps until the overlay wakes it otherwise.
+pub fn drive(backend: &mut dyn TintBackend, mut driver: TintDriver) {
+    let (wake_tx, wake_rx) = mpsc::channel();
+    overlay::set_waker(move || {
+        let _ = wake_tx.send(());
+    });
+
+    println!("Driving tint with the {} backend", backend.name());
+    let mut outputs = backend.outputs();
+    if !outputs.iter().any(|output| driver.shows_on(&output.name)) {
+        eprintln!("No output matched the overlay's monitor selection");
+    }
+    let mut last_tints = HashMap::new();
+    let mut next_refresh = Instant::now() + HOUSEKEEPING_INTERVAL;
+
+    loop {
+        let now = Instant::now();
+        if now >= next_refresh {
+            match backend.refresh_outputs() {
+                Ok(true) => {
+                    outputs = backend.outputs();
+                    println!("Outputs changed: {:?}", outputs.iter().map(|output| &output.name).collect::<Vec<_>>());
+                    // Paint the new outputs too
+                    last_tints.clear();
+                }
+                Ok(false) => {}
+                Err(e) => eprintln!("{}: failed to refresh outputs: {}", backend.name(), e),
+            }
+            driver.set_fullscreen(backend.fullscreen_outputs());
+            next_refresh = now + HOUSEKEEPING_INTERVAL;
+        }
+
+        match driver.frame(now, &outputs) {
+            Frame::Paint(tints) => {
+                let changed: Vec<(OutputId, Tint)> = tints
+                    .into_iter()
+                    .filter(|(output, tint)| last_tints.get(output) != Some(tint))
+                    .collect();
+                backend.apply_each(&changed);
+                last_tints.extend(changed);
+            }
+            Frame::Exit => {
+                backend.release();
+                return;
+            }
+        }
+
+        let timeout = if driver.is_animating(now) {
+            FRAME_INTERVAL
+        } else {
+            next_refresh.saturating_duration_since(now)
+        };
+        let _ = wake_rx.recv_timeout(timeout);
+        // Coalesce wake-ups that piled up meanwhile
+        while wake_rx.try_recv().is_ok() {}
+    }
+}
+
+// Pick the backend for this platform and session and run it until the overlay exits
+pub fn run(driver: TintDriver) {
+    #[cfg(target_os = "windows")]
+    layered::run(driver);
+
+    #[cfg(target_os = "linux")]
+    {
+        // Under XWayland the X11 ramps don't reach the real outputs, so try Wayland first
+        if std::env::var_os("WAYLAND_DISPLAY").is_some() {
+            match wayland::WaylandBackend::connect() {
+                Ok(mut backend) => return drive(&mut backend, driver),
+                Err(e) => eprintln!("Failed to start Wayland gamma backend: {}", e),
+            }
+        }
+        match x11::X11Backend::connect() {
+            Ok(mut backend) => drive(&mut backend, driver),
+            Err(e) => eprintln!("Failed to start X11 gamma backend: {}", e),
+        }
+    }
+
+    #[cfg(not(any(target_os = "windows", target_os = "linux")))]
+    {
+        let _ = driver;
+        eprintln!("No tint backend available on this platform");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn identity_ramp_runs_from_black_to_white() {
+        let ramp = identity_ramp(256);
+        assert_eq!(ramp.len(), 256);
+        assert_eq!(ramp[0], 0);
+        assert_eq!(ramp[255], u16::MAX);
+        assert_eq!(ramp[1], 257);
+        assert!(ramp.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // Degenerate sizes don't divide by zero
+        assert_eq!(identity_ramp(1), [0]);
+        assert!(identity_ramp(0).is_empty());
+    }
+
+    #[test]
+    fn scale_ramp_scales_and_clamps_the_gain() {
+        let ramp = [0, 1000, 40000, u16::MAX];
+        assert_eq!(scale_ramp(&ramp, 1.0), ramp);
+        assert_eq!(scale_ramp(&ramp, 0.5), [0, 500, 20000, 32768]);
+        assert_eq!(scale_ramp(&ramp, 0.0), [0, 0, 0, 0]);
+        // Out-of-range gains can't brighten or wrap around
+        assert_eq!(scale_ramp(&ramp, 1.5), ramp);
+        assert_eq!(scale_ramp(&ramp, -1.0), [0, 0, 0, 0]);
+    }
+
+    #[test]
+    fn scale_ramp_keeps_a_calibrated_curve() {
+        // A calibration curve that isn't linear stays proportional to itself
+        let calibrated = [0, 3000, 12000, 50000];
+        assert_eq!(scale_ramp(&calibrated, 0.25), [0, 750, 3000, 12500]);
+    }
+}
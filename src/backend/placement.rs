@@ -0,0 +1,193 @@
+// Where the layered-window backend puts its windows, worked out on plain monitor data so that
+// mixed-DPI setups can be checked without winit or a real desktop.
+//
+// Positions and sizes are physical pixels in desktop coordinates, exactly as winit reports them.
+// The process is per-monitor DPI aware, so a window given its monitor's physical rectangle covers
+// that monitor whatever the scale factor. Logical units would be multiplied by the scale factor of
+// whichever monitor the window lands on, and a scale change alone would look like a resize.
+
+use super::{Output, OutputId};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Monitor {
+    pub name: String,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    // For logging only, see above
+    pub scale_factor: f64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Placement {
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+}
+
+impl Placement {
+    pub fn of(monitor: &Monitor) -> Self {
+        Self { position: monitor.position, size: monitor.size }
+    }
+}
+
+// One step towards a window per monitor
+#[derive(Clone, Debug, PartialEq)]
+pub enum Action {
+    Create(Monitor),
+    // Update the output first: moving the window can raise ScaleFactorChanged right away, and
+    // size_after_scale_change has to see where the window is going
+    Move(OutputId, Placement),
+    Close(OutputId),
+}
+
+// What to do to the windows covering `outputs` so that each of `monitors` has exactly one
+pub fn plan(outputs: &[Output], monitors: &[Monitor]) -> Vec<Action> {
+    let mut actions = Vec::new();
+    for output in outputs {
+        match monitors.iter().find(|monitor| same_monitor(monitor, output)) {
+            None => actions.push(Action::Close(output.id)),
+            Some(monitor) => {
+                let placement = Placement::of(monitor);
+                if placement.position != output.position || placement.size != output.size {
+                    actions.push(Action::Move(output.id, placement));
+                }
+            }
+        }
+    }
+    for monitor in monitors {
+        if !outputs.iter().any(|output| same_monitor(monitor, output)) {
+            actions.push(Action::Create(monitor.clone()));
+        }
+    }
+    actions
+}
+
+// Moving onto a monitor with another scale factor, or changing a monitor's scale, makes Windows
+// suggest the window's size times the ratio of the two scales. The window has to stay exactly the
+// size of its monitor instead, as last seen; `output` only if that monitor has gone meanwhile.
+pub fn size_after_scale_change(monitors: &[Monitor], output: &Output) -> (u32, u32) {
+    monitors
+        .iter()
+        .find(|monitor| same_monitor(monitor, output))
+        .map_or(output.size, |monitor| monitor.size)
+}
+
+// Monitors are matched by name; winit has no more stable identifier
+pub fn same_monitor(monitor: &Monitor, output: &Output) -> bool {
+    monitor.name == output.name
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn monitor(name: &str, position: (i32, i32), size: (u32, u32), scale_factor: f64) -> Monitor {
+        Monitor { name: name.to_owned(), position, size, scale_factor }
+    }
+
+    // A 100% primary screen with a 150% screen to its left, top edges not aligned
+    fn monitors() -> Vec<Monitor> {
+        vec![
+            monitor("DISPLAY1", (0, 0), (1920, 1080), 1.0),
+            monitor("DISPLAY2", (-2560, -360), (2560, 1440), 1.5),
+        ]
+    }
+
+    // The windows plan() asked for, as the backend would have created them
+    fn created(actions: &[Action]) -> Vec<Output> {
+        actions
+            .iter()
+            .enumerate()
+            .filter_map(|(id, action)| match action {
+                Action::Create(monitor) => Some(Output {
+                    id: id as OutputId,
+                    name: monitor.name.clone(),
+                    position: monitor.position,
+                    size: monitor.size,
+                }),
+                _ => None,
+            })
+            .collect()
+    }
+
+    // What Windows suggests in WM_DPICHANGED: the same logical size at the new scale
+    fn suggested_size(size: (u32, u32), from: f64, to: f64) -> (u32, u32) {
+        ((size.0 as f64 * to / from).round() as u32, (size.1 as f64 * to / from).round() as u32)
+    }
+
+    #[test]
+    fn each_monitor_is_covered_in_physical_pixels() {
+        let monitors = monitors();
+        let outputs = created(&plan(&[], &monitors));
+        assert_eq!(outputs.len(), 2);
+
+        assert_eq!((outputs[0].position, outputs[0].size), ((0, 0), (1920, 1080)));
+        // Not 1707x960 at -1707,-240, which would leave a third of the scaled screen untinted
+        assert_eq!((outputs[1].position, outputs[1].size), ((-2560, -360), (2560, 1440)));
+        // Side by side: no gap and no overlap between the two windows
+        assert_eq!(outputs[1].position.0 + outputs[1].size.0 as i32, outputs[0].position.0);
+
+        // Nothing left to do once they exist
+        assert_eq!(plan(&outputs, &monitors), Vec::new());
+    }
+
+    #[test]
+    fn monitor_changes_move_create_and_close_windows() {
+        let mut monitors = monitors();
+        let outputs = created(&plan(&[], &monitors));
+
+        // The scaled screen is dragged below the primary one, and a third one is plugged in
+        monitors[1].position = (0, 1080);
+        monitors.push(monitor("DISPLAY3", (1920, 0), (1280, 1024), 1.25));
+        assert_eq!(
+            plan(&outputs, &monitors),
+            vec![
+                Action::Move(outputs[1].id, Placement { position: (0, 1080), size: (2560, 1440) }),
+                Action::Create(monitors[2].clone()),
+            ]
+        );
+
+        monitors.remove(1);
+        assert!(plan(&outputs, &monitors).contains(&Action::Close(outputs[1].id)));
+    }
+
+    #[test]
+    fn changing_only_the_scale_moves_nothing() {
+        let mut monitors = monitors();
+        let outputs = created(&plan(&[], &monitors));
+
+        // The primary screen goes from 100% to 150% in the display settings
+        monitors[0].scale_factor = 1.5;
+        assert_eq!(plan(&outputs, &monitors), Vec::new());
+
+        // Windows offers 2880x1620 for the primary's window; it keeps covering just its monitor
+        assert_eq!(suggested_size(outputs[0].size, 1.0, 1.5), (2880, 1620));
+        assert_eq!(size_after_scale_change(&monitors, &outputs[0]), (1920, 1080));
+    }
+
+    #[test]
+    fn window_moving_between_scales_keeps_its_monitors_size() {
+        let mut monitors = monitors();
+        let mut outputs = created(&plan(&[], &monitors));
+
+        // The two screens swap places: the 100% window now starts out over the 150% screen and
+        // the other way round, and each crosses the other's scale on its way
+        monitors[0].position = (-1920, 0);
+        monitors[1].position = (0, -360);
+        for action in plan(&outputs, &monitors) {
+            let Action::Move(id, placement) = action else { panic!("unexpected {:?}", action) };
+            let output = outputs.iter_mut().find(|output| output.id == id).unwrap();
+            output.position = placement.position;
+            output.size = placement.size;
+        }
+
+        // 150% -> 100% for the scaled screen's window, 100% -> 150% for the primary's
+        assert_eq!(suggested_size(outputs[1].size, 1.5, 1.0), (1707, 960));
+        assert_eq!(size_after_scale_change(&monitors, &outputs[1]), (2560, 1440));
+        assert_eq!(suggested_size(outputs[0].size, 1.0, 1.5), (2880, 1620));
+        assert_eq!(size_after_scale_change(&monitors, &outputs[0]), (1920, 1080));
+
+        // A window whose monitor is gone keeps its own size until it is closed
+        monitors.remove(0);
+        assert_eq!(size_after_scale_change(&monitors, &outputs[0]), (1920, 1080));
+    }
+}
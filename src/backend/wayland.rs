@@ -0,0 +1,310 @@
+// Wayland backend for wlroots compositors (Sway, Hyprland, ...): one zwlr_gamma_control_v1
+// per wl_output. Clients can't draw over other windows on Wayland, so like the X11 backend
+// this tints through the gamma ramps. The compositor restores the original ramps as soon as
+// the gamma control is destroyed, including when we crash. Outputs that are plugged in later
+// show up as new wl_output globals and get their own gamma control.
+
+use std::io::{Seek, SeekFrom, Write};
+use std::os::fd::AsFd;
+use wayland_client::globals::{registry_queue_init, GlobalListContents};
+use wayland_client::protocol::{wl_output, wl_registry};
+use wayland_client::{Connection, Dispatch, EventQueue, Proxy, QueueHandle, WEnum};
+use wayland_protocols_wlr::gamma_control::v1::client::{
+    zwlr_gamma_control_manager_v1::ZwlrGammaControlManagerV1,
+    zwlr_gamma_control_v1::{self, ZwlrGammaControlV1},
+};
+
+use super::{identity_ramp, scale_ramp, BackendError, Output, OutputId, TintBackend};
+use crate::color::Tint;
+
+struct WaylandOutput {
+    wl_output: wl_output::WlOutput,
+    control: Option<ZwlrGammaControlV1>,
+    output: Output,
+    // Entries per channel, announced by the compositor; 0 until then
+    gamma_size: u32,
+    failed: bool,
+}
+
+#[derive(Default)]
+struct State {
+    manager: Option<ZwlrGammaControlManagerV1>,
+    outputs: Vec<WaylandOutput>,
+    // Outputs were added, removed or reconfigured since the last refresh
+    changed: bool,
+}
+
+impl State {
+    fn output_mut(&mut self, id: OutputId) -> Option<&mut WaylandOutput> {
+        self.outputs.iter_mut().find(|output| output.output.id == id)
+    }
+
+    // Outputs are identified by their registry name
+    fn add_output(&mut self, registry: &wl_registry::WlRegistry, name: u32, version: u32, qh: &QueueHandle<Self>) {
+        let id = name as OutputId;
+        // Version 4 adds the output name
+        let wl_output: wl_output::WlOutput = registry.bind(name, version.min(4), qh, id);
+        let control = self.manager.as_ref().map(|manager| manager.get_gamma_control(&wl_output, qh, id));
+        self.outputs.push(WaylandOutput {
+            wl_output,
+            control,
+            output: Output {
+                id,
+                name: format!("output {}", name),
+                position: (0, 0),
+                size: (0, 0),
+            },
+            gamma_size: 0,
+            failed: false,
+        });
+        self.changed = true;
+    }
+
+    fn remove_output(&mut self, name: u32) {
+        let Some(index) = self.outputs.iter().position(|output| output.output.id == name as OutputId) else {
+            return;
+        };
+        let output = self.outputs.remove(index);
+        println!("Wayland output {} was removed", output.output.name);
+        if let Some(control) = output.control {
+            control.destroy();
+        }
+        if output.wl_output.version() >= 3 {
+            output.wl_output.release();
+        }
+        self.changed = true;
+    }
+
+    fn usable(output: &WaylandOutput) -> bool {
+        output.control.is_some() && !output.failed && output.gamma_size > 0
+    }
+}
+
+pub struct WaylandBackend {
+    conn: Connection,
+    queue: EventQueue<State>,
+    state: State,
+}
+
+fn wayland_error(e: impl std::fmt::Display) -> BackendError {
+    BackendError(format!("Wayland: {}", e))
+}
+
+impl WaylandBackend {
+    pub fn connect() -> Result<Self, BackendError> {
+        let conn = Connection::connect_to_env().map_err(wayland_error)?;
+        let (globals, mut queue) = registry_queue_init::<State>(&conn).map_err(wayland_error)?;
+        let qh = queue.handle();
+
+        let manager: ZwlrGammaControlManagerV1 = globals
+            .bind(&qh, 1..=1, ())
+            .map_err(|_| BackendError("Wayland: compositor does not support wlr-gamma-control".to_owned()))?;
+
+        let mut state = State { manager: Some(manager), ..State::default() };
+        for global in globals.contents().clone_list() {
+            if global.interface == wl_output::WlOutput::interface().name {
+                state.add_output(globals.registry(), global.name, global.version, &qh);
+            }
+        }
+
+        // Output names and geometry plus the gamma sizes
+        queue.roundtrip(&mut state).map_err(wayland_error)?;
+
+        for output in state.outputs.iter().filter(|output| output.failed) {
+            eprintln!("Wayland: gamma control for {} is unavailable", output.output.name);
+        }
+        if !state.outputs.iter().any(State::usable) {
+            return Err(BackendError("Wayland: no outputs with gamma control".to_owned()));
+        }
+        state.changed = false;
+
+        Ok(Self { conn, queue, state })
+    }
+}
+
+// The compositor reads the red, green and blue ramps back to back from a file descriptor
+fn ramp_file(ramps: &[Vec<u16>; 3]) -> std::io::Result<std::fs::File> {
+    let mut file = tempfile::tempfile()?;
+    let bytes: Vec<u8> = ramps.iter().flatten().flat_map(|value| value.to_ne_bytes()).collect();
+    file.write_all(&bytes)?;
+    file.flush()?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(file)
+}
+
+impl TintBackend for WaylandBackend {
+    fn name(&self) -> &'static str {
+        "wayland-gamma"
+    }
+
+    fn outputs(&self) -> Vec<Output> {
+        self.state.outputs.iter().filter(|output| State::usable(output)).map(|output| output.output.clone()).collect()
+    }
+
+    fn apply(&mut self, output: OutputId, tint: Tint) -> Result<(), BackendError> {
+        // Pick up `failed` events and output changes since the last frame
+        self.queue.dispatch_pending(&mut self.state).map_err(wayland_error)?;
+
+        let state = self.state
+            .output_mut(output)
+            .ok_or_else(|| BackendError(format!("no Wayland output {}", output)))?;
+        if state.failed {
+            return Err(BackendError(format!("gamma control for {} failed", state.output.name)));
+        }
+        let Some(control) = &state.control else {
+            return Err(BackendError(format!("no gamma control for {}", state.output.name)));
+        };
+
+        let identity = identity_ramp(state.gamma_size as usize);
+        let gains = tint.channel_gains();
+        let ramps = [
+            scale_ramp(&identity, gains[0]),
+            scale_ramp(&identity, gains[1]),
+            scale_ramp(&identity, gains[2]),
+        ];
+        let file = ramp_file(&ramps).map_err(wayland_error)?;
+        control.set_gamma(file.as_fd());
+        self.conn.flush().map_err(wayland_error)
+    }
+
+    fn refresh_outputs(&mut self) -> Result<bool, BackendError> {
+        self.conn.flush().map_err(wayland_error)?;
+        // Read whatever the compositor sent without blocking
+        if let Some(guard) = self.queue.prepare_read() {
+            let _ = guard.read();
+        }
+        self.queue.dispatch_pending(&mut self.state).map_err(wayland_error)?;
+        Ok(std::mem::take(&mut self.state.changed))
+    }
+
+    fn release(&mut self) {
+        for output in &mut self.state.outputs {
+            if let Some(control) = output.control.take() {
+                control.destroy();
+            }
+        }
+        if let Err(e) = self.conn.flush() {
+            eprintln!("Failed to release Wayland gamma controls: {}", e);
+        }
+        // Make sure the compositor has seen the destroys before we exit
+        let _ = self.queue.roundtrip(&mut self.state);
+    }
+}
+
+impl Drop for WaylandBackend {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+impl Dispatch<wl_registry::WlRegistry, GlobalListContents> for State {
+    fn event(
+        state: &mut Self,
+        registry: &wl_registry::WlRegistry,
+        event: wl_registry::Event,
+        _: &GlobalListContents,
+        _: &Connection,
+        qh: &QueueHandle<Self>,
+    ) {
+        match event {
+            wl_registry::Event::Global { name, interface, version } if interface == wl_output::WlOutput::interface().name => {
+                println!("Wayland output {} was added", name);
+                state.add_output(registry, name, version, qh);
+            }
+            wl_registry::Event::GlobalRemove { name } => state.remove_output(name),
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<wl_output::WlOutput, OutputId> for State {
+    fn event(
+        state: &mut Self,
+        _: &wl_output::WlOutput,
+        event: wl_output::Event,
+        id: &OutputId,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*id) else { return };
+        match event {
+            wl_output::Event::Geometry { x, y, .. } => output.output.position = (x, y),
+            wl_output::Event::Mode { flags: WEnum::Value(flags), width, height, .. }
+                if flags.contains(wl_output::Mode::Current) =>
+            {
+                output.output.size = (width.max(0) as u32, height.max(0) as u32);
+            }
+            wl_output::Event::Name { name } => output.output.name = name,
+            wl_output::Event::Done => state.changed = true,
+            _ => {}
+        }
+    }
+}
+
+impl Dispatch<ZwlrGammaControlManagerV1, ()> for State {
+    fn event(
+        _: &mut Self,
+        _: &ZwlrGammaControlManagerV1,
+        _: <ZwlrGammaControlManagerV1 as Proxy>::Event,
+        _: &(),
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+    }
+}
+
+impl Dispatch<ZwlrGammaControlV1, OutputId> for State {
+    fn event(
+        state: &mut Self,
+        control: &ZwlrGammaControlV1,
+        event: zwlr_gamma_control_v1::Event,
+        id: &OutputId,
+        _: &Connection,
+        _: &QueueHandle<Self>,
+    ) {
+        let Some(output) = state.output_mut(*id) else { return };
+        match event {
+            zwlr_gamma_control_v1::Event::GammaSize { size } => {
+                output.gamma_size = size;
+                state.changed = true;
+            }
+            // Another client holds the output's gamma, or the output went away
+            // The protocol wants failed objects destroyed by the client
+            zwlr_gamma_control_v1::Event::Failed => {
+                control.destroy();
+                output.failed = true;
+                output.control = None;
+                state.changed = true;
+            }
+            _ => {}
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn ramp_file_holds_red_green_blue_in_native_order() {
+        let ramps = [vec![0, 1, 0xffff], vec![2, 3, 0x8000], vec![4, 5, 0x1234]];
+        let mut file = ramp_file(&ramps).unwrap();
+
+        // The compositor reads from the current position, so it has to be back at the start
+        assert_eq!(file.stream_position().unwrap(), 0);
+        assert_eq!(file.metadata().unwrap().len(), 3 * 3 * 2);
+
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).unwrap();
+        let values: Vec<u16> = bytes.chunks_exact(2).map(|pair| u16::from_ne_bytes([pair[0], pair[1]])).collect();
+        assert_eq!(values, [0, 1, 0xffff, 2, 3, 0x8000, 4, 5, 0x1234]);
+    }
+
+    #[test]
+    fn ramp_file_matches_the_size_the_compositor_asked_for() {
+        let ramps = [identity_ramp(256), identity_ramp(256), identity_ramp(256)];
+        let file = ramp_file(&ramps).unwrap();
+        assert_eq!(file.metadata().unwrap().len(), 256 * 3 * 2);
+    }
+}
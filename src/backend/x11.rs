@@ -0,0 +1,264 @@
+// X11 backend: scales each CRTC's gamma ramps through XRandR, so the tint is a real color
+// adjustment rather than a layer on top. The ramps found when a CRTC first shows up are
+// restored on release. Monitors coming and going are picked up by re-reading the screen
+// resources, which doesn't make the server probe the outputs again.
+
+use std::collections::hash_map::{Entry, HashMap};
+use x11rb::connection::Connection;
+use x11rb::protocol::randr::{self, ConnectionExt as _};
+use x11rb::rust_connection::RustConnection;
+
+use super::{identity_ramp, scale_ramp, BackendError, Output, OutputId, TintBackend};
+use crate::color::Tint;
+
+type Ramps = [Vec<u16>; 3];
+
+struct CrtcState {
+    crtc: randr::Crtc,
+    output: Output,
+    // Ramps as we found them, [red, green, blue], at the CRTC's current ramp size
+    original: Ramps,
+}
+
+// Ramps of every CRTC as first seen, kept while it is switched off: by the time it comes back
+// its ramps may still be tinted by us, so reading them again would make the tint "original"
+#[derive(Default)]
+struct Originals(HashMap<randr::Crtc, Ramps>);
+
+impl Originals {
+    fn get_or_read(
+        &mut self,
+        crtc: randr::Crtc,
+        read: impl FnOnce() -> Result<Ramps, BackendError>,
+    ) -> Result<&Ramps, BackendError> {
+        match self.0.entry(crtc) {
+            Entry::Occupied(entry) => Ok(entry.into_mut()),
+            Entry::Vacant(entry) => Ok(entry.insert(read()?)),
+        }
+    }
+}
+
+pub struct X11Backend {
+    conn: RustConnection,
+    root: u32,
+    crtcs: Vec<CrtcState>,
+    originals: Originals,
+}
+
+fn x11_error(e: impl std::fmt::Display) -> BackendError {
+    BackendError(format!("X11: {}", e))
+}
+
+impl X11Backend {
+    pub fn connect() -> Result<Self, BackendError> {
+        let (conn, screen_num) = x11rb::connect(None).map_err(x11_error)?;
+        let root = conn.setup().roots[screen_num].root;
+
+        // Gamma per CRTC needs RandR 1.2
+        let version = conn.randr_query_version(1, 2).map_err(x11_error)?.reply().map_err(x11_error)?;
+        if (version.major_version, version.minor_version) < (1, 2) {
+            return Err(BackendError(format!(
+                "RandR {}.{} is too old, 1.2 is required",
+                version.major_version, version.minor_version
+            )));
+        }
+
+        let mut backend = Self { conn, root, crtcs: Vec::new(), originals: Originals::default() };
+        backend.crtcs = backend.enumerate_crtcs()?;
+        if backend.crtcs.is_empty() {
+            return Err(BackendError("X11: no active CRTCs found".to_owned()));
+        }
+        Ok(backend)
+    }
+
+    // Active CRTCs, with the ramps they had before we touched them
+    fn enumerate_crtcs(&mut self) -> Result<Vec<CrtcState>, BackendError> {
+        let conn = &self.conn;
+        let resources = conn.randr_get_screen_resources_current(self.root)
+            .map_err(x11_error)?
+            .reply()
+            .map_err(x11_error)?;
+
+        let mut crtcs = Vec::new();
+        for &crtc in &resources.crtcs {
+            let info = conn.randr_get_crtc_info(crtc, resources.config_timestamp)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?;
+
+            // Not driving anything
+            if info.mode == 0 || info.outputs.is_empty() {
+                continue;
+            }
+
+            let name = conn.randr_get_output_info(info.outputs[0], resources.config_timestamp)
+                .map_err(x11_error)?
+                .reply()
+                .map(|output| String::from_utf8_lossy(&output.name).into_owned())
+                .unwrap_or_else(|_| format!("CRTC {}", crtc));
+
+            let original = self.originals.get_or_read(crtc, || {
+                let gamma = conn.randr_get_crtc_gamma(crtc)
+                    .map_err(x11_error)?
+                    .reply()
+                    .map_err(x11_error)?;
+                Ok([gamma.red, gamma.green, gamma.blue])
+            })?;
+            // A mode change can change the ramp size, then the saved ramps have to be stretched
+            let size = conn.randr_get_crtc_gamma_size(crtc)
+                .map_err(x11_error)?
+                .reply()
+                .map_err(x11_error)?
+                .size as usize;
+            let original = original.clone().map(|ramp| fit_ramp(&ramp, size));
+
+            crtcs.push(CrtcState {
+                crtc,
+                output: Output {
+                    id: crtc as OutputId,
+                    name,
+                    position: (info.x as i32, info.y as i32),
+                    size: (info.width as u32, info.height as u32),
+                },
+                original,
+            });
+        }
+        Ok(crtcs)
+    }
+
+    fn set_gamma(&self, crtc: randr::Crtc, ramps: &[Vec<u16>; 3]) -> Result<(), BackendError> {
+        self.conn.randr_set_crtc_gamma(crtc, &ramps[0], &ramps[1], &ramps[2])
+            .map_err(x11_error)?
+            .check()
+            .map_err(x11_error)
+    }
+}
+
+impl TintBackend for X11Backend {
+    fn name(&self) -> &'static str {
+        "x11-gamma"
+    }
+
+    fn outputs(&self) -> Vec<Output> {
+        self.crtcs.iter().map(|state| state.output.clone()).collect()
+    }
+
+    fn apply(&mut self, output: OutputId, tint: Tint) -> Result<(), BackendError> {
+        let state = self.crtcs
+            .iter()
+            .find(|state| state.output.id == output)
+            .ok_or_else(|| BackendError(format!("no CRTC for output {}", output)))?;
+
+        self.set_gamma(state.crtc, &tinted_ramps(&state.original, tint))
+    }
+
+    fn refresh_outputs(&mut self) -> Result<bool, BackendError> {
+        let crtcs = self.enumerate_crtcs()?;
+        let changed = crtcs.len() != self.crtcs.len()
+            || crtcs.iter().zip(&self.crtcs).any(|(new, old)| new.output != old.output);
+        self.crtcs = crtcs;
+        Ok(changed)
+    }
+
+    fn release(&mut self) {
+        for state in &self.crtcs {
+            if let Err(e) = self.set_gamma(state.crtc, &state.original) {
+                eprintln!("Failed to restore gamma for {}: {}", state.output.name, e);
+            }
+        }
+        let _ = self.conn.flush();
+        self.crtcs.clear();
+    }
+}
+
+fn tinted_ramps(original: &Ramps, tint: Tint) -> Ramps {
+    let gains = tint.channel_gains();
+    [
+        scale_ramp(&original[0], gains[0]),
+        scale_ramp(&original[1], gains[1]),
+        scale_ramp(&original[2], gains[2]),
+    ]
+}
+
+// Resample a ramp to `size` entries, linearly between the existing ones
+fn fit_ramp(ramp: &[u16], size: usize) -> Vec<u16> {
+    if ramp.len() == size {
+        return ramp.to_vec();
+    }
+    if ramp.len() < 2 {
+        return identity_ramp(size);
+    }
+    let last = (ramp.len() - 1) as f32;
+    let new_last = size.saturating_sub(1).max(1) as f32;
+    (0..size)
+        .map(|i| {
+            let x = i as f32 / new_last * last;
+            let below = x.floor() as usize;
+            let above = (below + 1).min(ramp.len() - 1);
+            let t = x - below as f32;
+            (ramp[below] as f32 + (ramp[above] as f32 - ramp[below] as f32) * t).round() as u16
+        })
+        .collect()
+}
+
+impl Drop for X11Backend {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn identity() -> Ramps {
+        [identity_ramp(256), identity_ramp(256), identity_ramp(256)]
+    }
+
+    #[test]
+    fn no_tint_gives_back_the_original_ramps() {
+        // A calibrated curve, not a straight line
+        let original: Ramps = [vec![0, 9000, 30000, 65535], vec![0, 20000, 40000, 60000], vec![100, 200, 300, 400]];
+        assert_eq!(tinted_ramps(&original, Tint { rgb: [255, 0, 0], alpha: 0, dim: 0 }), original);
+    }
+
+    #[test]
+    fn red_tint_keeps_red_and_scales_the_rest() {
+        let ramps = tinted_ramps(&identity(), Tint { rgb: [255, 0, 0], alpha: 255, dim: 0 });
+        assert_eq!(ramps[0], identity_ramp(256));
+        assert!(ramps[1].iter().chain(&ramps[2]).all(|&value| value == 0));
+
+        let ramps = tinted_ramps(&identity(), Tint { rgb: [255, 0, 0], alpha: 0, dim: 128 });
+        let keep = 1.0 - 128.0 / 255.0;
+        assert_eq!(ramps[2][255], (u16::MAX as f32 * keep).round() as u16);
+    }
+
+    #[test]
+    fn fit_ramp_stretches_to_a_new_size() {
+        assert_eq!(fit_ramp(&[0, 1000, 5000], 3), [0, 1000, 5000]);
+        assert_eq!(fit_ramp(&[0, 1000, 5000], 5), [0, 500, 1000, 3000, 5000]);
+        assert_eq!(fit_ramp(&[0, 1000, 5000], 2), [0, 5000]);
+        // An identity ramp stays one
+        let stretched = fit_ramp(&identity_ramp(256), 1024);
+        assert_eq!(stretched.len(), 1024);
+        assert!(stretched.iter().zip(identity_ramp(1024)).all(|(&a, b)| a.abs_diff(b) <= 1));
+        // Nothing to stretch
+        assert_eq!(fit_ramp(&[], 4), identity_ramp(4));
+    }
+
+    #[test]
+    fn originals_are_never_replaced_by_our_own_ramps() {
+        let mut originals = Originals::default();
+        let first = originals.get_or_read(63, || Ok(identity())).unwrap().clone();
+        assert_eq!(first, identity());
+
+        // The CRTC is switched off and on again while tinted; what it reads now is our tint
+        let tinted = tinted_ramps(&identity(), Tint { rgb: [255, 0, 0], alpha: 200, dim: 0 });
+        let again = originals.get_or_read(63, || Ok(tinted.clone())).unwrap();
+        assert_eq!(again, &identity());
+
+        // Each CRTC has its own, and a failed read isn't remembered
+        assert!(originals.get_or_read(64, || Err(BackendError("gone".to_owned()))).is_err());
+        assert_eq!(originals.get_or_read(64, || Ok(tinted.clone())).unwrap(), &tinted);
+    }
+}
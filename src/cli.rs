@@ -0,0 +1,278 @@
+// Command line interface: `redshift <command>` talks to the running controller through the
+// single-instance channel, so it can be bound to hotkey daemons and used from scripts.
+// Without a command, RedShift starts the controller as usual.
+
+use clap::{ArgGroup, Parser, Subcommand};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::color;
+use crate::controller::{self, ControllerStatus};
+use crate::instance::{self, InstanceReply, InstanceRequest};
+use crate::overlay::TintMode;
+
+// Exit codes; clap itself exits with 2 on bad usage
+pub const EXIT_OK: i32 = 0;
+pub const EXIT_FAILED: i32 = 1;
+pub const EXIT_NOT_RUNNING: i32 = 3;
+
+#[derive(Parser, Debug)]
+#[command(
+    name = "redshift",
+    version,
+    about = "Tint the screen red or warm for late-night use",
+    after_help = "Run without a command to start RedShift (or bring up the settings of the running instance).\n\n\
+                  Exit codes: 0 success, 1 request failed, 2 bad usage, 3 RedShift is not running.",
+    args_conflicts_with_subcommands = true
+)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<CliCommand>,
+    /// Start with this intensity, or set it on the running instance. Kept working for shortcuts
+    /// made before the subcommands existed; `set --opacity` is the documented way.
+    #[arg(long, hide = true)]
+    pub opacity: Option<u8>,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum CliCommand {
+    /// Change settings of the running instance
+    #[command(group(ArgGroup::new("setting").required(true).multiple(true)))]
+    Set {
+        /// Overlay intensity, 90 (faint) to 200 (strongest)
+        #[arg(long, group = "setting", value_parser = clap::value_parser!(u8).range(90..=200))]
+        opacity: Option<u8>,
+        /// Color temperature in Kelvin, 1000 to 6500
+        #[arg(long, group = "setting", value_parser = clap::value_parser!(u32).range(1000..=6500))]
+        temperature: Option<u32>,
+        /// Whether the slider controls red intensity or color temperature
+        #[arg(long, group = "setting", value_enum)]
+        mode: Option<TintMode>,
+        /// Layer color for intensity mode, e.g. "#ff8000" for amber
+        #[arg(long, group = "setting", value_parser = color::parse_hex)]
+        color: Option<[u8; 3]>,
+        /// Screen brightness in percent, 20 (darkest) to 100 (no dimming)
+        #[arg(long, group = "setting", value_parser = clap::value_parser!(u8).range(20..=100))]
+        brightness: Option<u8>,
+    },
+    /// Turn the overlay on or off
+    Toggle,
+    /// Show the current settings and overlay state
+    Status {
+        /// Print machine-readable JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Apply a preset (day, evening, night or red) or one of your profiles
+    Preset {
+        name: String,
+    },
+    /// Manage named profiles
+    #[command(subcommand)]
+    Profile(ProfileCommand),
+    /// Hide the tint for a while; it comes back by itself
+    #[command(group(ArgGroup::new("length").required(true)))]
+    Pause {
+        /// Minutes to pause for, e.g. 5, 15 or 60
+        #[arg(long, group = "length", value_parser = clap::value_parser!(u32).range(1..=1440))]
+        minutes: Option<u32>,
+        /// Pause until the next sunrise (needs the schedule's coordinates)
+        #[arg(long, group = "length")]
+        until_sunrise: bool,
+    },
+    /// End a pause early
+    Resume,
+    /// Fade the overlay out and stop RedShift
+    Quit,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ProfileCommand {
+    /// List profiles
+    List {
+        /// Print machine-readable JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Switch to a profile
+    Use { name: String },
+    /// Create a profile from the current settings
+    Create { name: String },
+    /// Overwrite a profile with the current settings
+    Save { name: String },
+    /// Rename a profile
+    Rename { from: String, to: String },
+    /// Delete a profile
+    Delete { name: String },
+    /// Set the profile applied when RedShift starts
+    Default {
+        /// Profile name (omit when using --clear)
+        #[arg(required_unless_present = "clear")]
+        name: Option<String>,
+        /// Don't switch to any profile on startup
+        #[arg(long, conflicts_with = "name")]
+        clear: bool,
+    },
+}
+
+// Console programs get a console from the shell; we are a GUI program, so borrow the parent's
+#[cfg(target_os = "windows")]
+pub fn attach_console() {
+    use winapi::um::wincon::{AttachConsole, ATTACH_PARENT_PROCESS};
+    unsafe {
+        AttachConsole(ATTACH_PARENT_PROCESS);
+    }
+}
+
+#[cfg(not(target_os = "windows"))]
+pub fn attach_console() {}
+
+// Run a command against the running instance and return the process exit code
+pub fn run(command: CliCommand) -> i32 {
+    let json = matches!(
+        command,
+        CliCommand::Status { json: true } | CliCommand::Profile(ProfileCommand::List { json: true })
+    );
+    let request = match command {
+        CliCommand::Set { opacity, temperature, mode, color, brightness } => InstanceRequest::Set {
+            opacity,
+            color_temperature: temperature,
+            tint_mode: mode,
+            tint_color: color,
+            brightness,
+        },
+        CliCommand::Toggle => InstanceRequest::Toggle,
+        CliCommand::Status { .. } => InstanceRequest::Status,
+        // Profiles live in the running instance, so unknown names are reported from there
+        CliCommand::Preset { name } => InstanceRequest::Preset { name },
+        CliCommand::Profile(command) => match command {
+            ProfileCommand::List { .. } => InstanceRequest::ListProfiles,
+            ProfileCommand::Use { name } => InstanceRequest::UseProfile { name },
+            ProfileCommand::Create { name } => InstanceRequest::CreateProfile { name },
+            ProfileCommand::Save { name } => InstanceRequest::SaveProfile { name },
+            ProfileCommand::Rename { from, to } => InstanceRequest::RenameProfile { from, to },
+            ProfileCommand::Delete { name } => InstanceRequest::DeleteProfile { name },
+            ProfileCommand::Default { name, .. } => InstanceRequest::SetDefaultProfile { name },
+        },
+        CliCommand::Pause { minutes, .. } => InstanceRequest::Pause { minutes },
+        CliCommand::Resume => InstanceRequest::Resume,
+        CliCommand::Quit => InstanceRequest::Quit,
+    };
+
+    if !instance::is_running() {
+        eprintln!("RedShift is not running");
+        return EXIT_NOT_RUNNING;
+    }
+
+    match instance::forward(request) {
+        Ok(InstanceReply::Ok) => EXIT_OK,
+        Ok(InstanceReply::Status { status }) => {
+            if json {
+                match serde_json::to_string_pretty(&status) {
+                    Ok(text) => println!("{}", text),
+                    Err(e) => {
+                        eprintln!("Failed to serialize status: {}", e);
+                        return EXIT_FAILED;
+                    }
+                }
+            } else {
+                print_status(&status);
+            }
+            EXIT_OK
+        }
+        Ok(InstanceReply::Profiles { profiles, active, default }) => {
+            if json {
+                let listing = serde_json::json!({ "profiles": profiles, "active": active, "default": default });
+                println!("{:#}", listing);
+            } else {
+                for profile in &profiles {
+                    let marker = if active.as_ref() == Some(&profile.name) { '*' } else { ' ' };
+                    let color = match profile.tint_mode {
+                        TintMode::Intensity => color::to_hex(profile.tint_color),
+                        TintMode::Temperature => format!("{}K", profile.color_temperature),
+                    };
+                    let note = if default.as_ref() == Some(&profile.name) { "  (default)" } else { "" };
+                    println!(
+                        "{} {:<24} {:<10} opacity {}  brightness {}%{}",
+                        marker, profile.name, color, profile.opacity, profile.brightness, note
+                    );
+                }
+            }
+            EXIT_OK
+        }
+        Ok(InstanceReply::Error { message }) => {
+            eprintln!("RedShift refused the request: {}", message);
+            EXIT_FAILED
+        }
+        Err(e) => {
+            eprintln!("Could not reach the running RedShift: {}", e);
+            EXIT_NOT_RUNNING
+        }
+    }
+}
+
+fn print_status(status: &ControllerStatus) {
+    println!("Overlay:     {} ({})", if status.enabled { "on" } else { "off" }, status.overlay);
+    match status.tint_mode {
+        TintMode::Intensity => println!("Mode:        intensity, {}", color::to_hex(status.tint_color)),
+        TintMode::Temperature => println!("Mode:        temperature, {}K", status.color_temperature),
+    }
+    println!("Opacity:     {}", status.opacity);
+    println!("Brightness:  {}%", status.brightness);
+    if let Some(profile) = &status.active_profile {
+        println!("Profile:     {}", profile);
+    }
+    if let Some(rule) = &status.app_rule {
+        println!("App rule:    {}", rule);
+    }
+    if let Some(until) = status.paused_until {
+        let until = UNIX_EPOCH + Duration::from_secs(until);
+        let remaining = until.duration_since(SystemTime::now()).unwrap_or_default();
+        println!("Paused:      until {} ({} left)", controller::format_clock(until), controller::format_remaining(remaining));
+    }
+    if let Some(alpha) = status.overlay_alpha {
+        println!("Alpha now:   {}", alpha);
+    }
+    if let Some(problem) = &status.problem {
+        println!("Problem:     {}", problem);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+        Cli::try_parse_from(std::iter::once("redshift").chain(args.iter().copied()))
+    }
+
+    #[test]
+    fn old_opacity_flag_still_works() {
+        let cli = parse(&["--opacity", "150"]).unwrap();
+        assert_eq!(cli.opacity, Some(150));
+        assert!(cli.command.is_none());
+        // Out of range values used to be clamped by the controller, so they still parse
+        assert_eq!(parse(&["--opacity", "40"]).unwrap().opacity, Some(40));
+        assert!(parse(&["--opacity", "loud"]).is_err());
+    }
+
+    #[test]
+    fn old_opacity_flag_is_hidden_and_not_mixed_with_commands() {
+        let help = Cli::command().render_help().to_string();
+        assert!(!help.contains("--opacity"), "{}", help);
+        assert!(parse(&["--opacity", "150", "toggle"]).is_err());
+    }
+
+    #[test]
+    fn set_takes_the_documented_flags() {
+        let cli = parse(&["set", "--opacity", "150", "--color", "#ff8000"]).unwrap();
+        match cli.command {
+            Some(CliCommand::Set { opacity, color, .. }) => {
+                assert_eq!(opacity, Some(150));
+                assert_eq!(color, Some([255, 128, 0]));
+            }
+            other => panic!("expected set, got {:?}", other),
+        }
+        assert!(parse(&["set", "--opacity", "40"]).is_err());
+        assert!(parse(&["set"]).is_err());
+    }
+}
@@ -0,0 +1,208 @@
+// Color helpers shared by the overlay and the controller
+
+pub const MIN_TEMPERATURE: u32 = 1000;
+pub const MAX_TEMPERATURE: u32 = 6500;
+
+// Screen brightness in percent; below 20 the screen gets hard to read at all
+pub const MIN_BRIGHTNESS: u8 = 20;
+pub const MAX_BRIGHTNESS: u8 = 100;
+
+// Clamp a temperature to the range the slider offers
+pub fn clamp_temperature(kelvin: u32) -> u32 {
+    kelvin.clamp(MIN_TEMPERATURE, MAX_TEMPERATURE)
+}
+
+/// Converts a color temperature in Kelvin to an RGB tint.
+///
+/// Uses Tanner Helland's curve fit of the blackbody spectrum, which is accurate
+/// enough for a screen tint and needs nothing more than `ln` and `powf`.
+pub fn kelvin_to_rgb(kelvin: u32) -> [u8; 3] {
+    let temp = clamp_temperature(kelvin) as f64 / 100.0;
+
+    let red = if temp <= 66.0 {
+        255.0
+    } else {
+        329.698727446 * (temp - 60.0).powf(-0.1332047592)
+    };
+
+    let green = if temp <= 66.0 {
+        99.4708025861 * temp.ln() - 161.1195681661
+    } else {
+        288.1221695283 * (temp - 60.0).powf(-0.0755148492)
+    };
+
+    let blue = if temp >= 66.0 {
+        255.0
+    } else if temp <= 19.0 {
+        0.0
+    } else {
+        138.5177312231 * (temp - 10.0).ln() - 305.0447927307
+    };
+
+    [to_channel(red), to_channel(green), to_channel(blue)]
+}
+
+// How strongly a temperature tint should be applied: 0.0 at daylight, 1.0 at the warmest setting
+pub fn temperature_strength(kelvin: u32) -> f32 {
+    let kelvin = clamp_temperature(kelvin);
+    (MAX_TEMPERATURE - kelvin) as f32 / (MAX_TEMPERATURE - MIN_TEMPERATURE) as f32
+}
+
+pub fn clamp_brightness(brightness: u8) -> u8 {
+    brightness.clamp(MIN_BRIGHTNESS, MAX_BRIGHTNESS)
+}
+
+// How much black to lay over the screen for a brightness: 0 at 100%, 255 would be fully black
+pub fn brightness_dim(brightness: u8) -> u8 {
+    let darkening = (MAX_BRIGHTNESS - clamp_brightness(brightness)) as u16;
+    (darkening * 255 / MAX_BRIGHTNESS as u16) as u8
+}
+
+// "#ff8000" or "ff8000", for the command line
+pub fn parse_hex(text: &str) -> Result<[u8; 3], String> {
+    let digits = text.trim().trim_start_matches('#');
+    if digits.len() != 6 || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(format!("'{}' is not a color like #ff8000", text));
+    }
+    let channel = |index: usize| u8::from_str_radix(&digits[index..index + 2], 16).unwrap_or_default();
+    Ok([channel(0), channel(2), channel(4)])
+}
+
+pub fn to_hex(rgb: [u8; 3]) -> String {
+    format!("#{:02x}{:02x}{:02x}", rgb[0], rgb[1], rgb[2])
+}
+
+fn to_channel(value: f64) -> u8 {
+    value.round().clamp(0.0, 255.0) as u8
+}
+
+// What the overlay puts on a monitor: a solid color layer, with a black layer of strength
+// `dim` on top of it to darken the screen
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct Tint {
+    pub rgb: [u8; 3],
+    pub alpha: u8,
+    pub dim: u8,
+}
+
+impl Tint {
+    pub fn new(rgb: [u8; 3], alpha: u8) -> Self {
+        Self { rgb, alpha, dim: 0 }
+    }
+
+    pub fn with_dim(self, dim: u8) -> Self {
+        Self { dim, ..self }
+    }
+
+    // Interpolate towards `other`, t in 0.0..=1.0
+    pub fn lerp(&self, other: &Tint, t: f32) -> Tint {
+        let mix = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+        Tint {
+            rgb: [
+                mix(self.rgb[0], other.rgb[0]),
+                mix(self.rgb[1], other.rgb[1]),
+                mix(self.rgb[2], other.rgb[2]),
+            ],
+            alpha: mix(self.alpha, other.alpha),
+            dim: mix(self.dim, other.dim),
+        }
+    }
+
+    // The tint and the black layer merged into the one color and alpha a layered window
+    // can show. Over a screen color S the two layers give (1 - d)(a*C + (1 - a)*S), which is
+    // a single layer with alpha 1 - (1 - d)(1 - a) and color (1 - d)*a*C divided by that alpha.
+    #[cfg(target_os = "windows")]
+    pub fn layer(&self) -> ([u8; 3], u8) {
+        if self.dim == 0 {
+            return (self.rgb, self.alpha);
+        }
+        let alpha = self.alpha as f32 / 255.0;
+        let keep = 1.0 - self.dim as f32 / 255.0;
+        let combined = 1.0 - keep * (1.0 - alpha);
+        let rgb = self.rgb.map(|channel| (keep * alpha * channel as f32 / combined).round() as u8);
+        (rgb, (combined * 255.0).round() as u8)
+    }
+
+    // Per-channel multipliers that approximate blending this tint over the screen, for
+    // backends that scale the display's gamma ramps instead of drawing a layer on top.
+    // Blending color C with alpha a over white gives a*C + (1 - a), which is the gain; the
+    // dim layer then scales all of it down.
+    pub fn channel_gains(&self) -> [f32; 3] {
+        let alpha = self.alpha as f32 / 255.0;
+        let keep = 1.0 - self.dim as f32 / 255.0;
+        self.rgb.map(|channel| keep * (1.0 - alpha * (1.0 - channel as f32 / 255.0)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_gains(actual: [f32; 3], expected: [f32; 3]) {
+        for (a, e) in actual.iter().zip(expected) {
+            assert!((a - e).abs() < 1e-3, "{:?} != {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn daylight_is_neutral_white() {
+        // The curve fit lands within a few steps of pure white
+        let [red, green, blue] = kelvin_to_rgb(6500);
+        assert_eq!(red, 255);
+        assert!(green >= 250 && blue >= 248, "{:?}", [red, green, blue]);
+        assert_eq!(temperature_strength(6500), 0.0);
+    }
+
+    #[test]
+    fn candlelight_is_the_warm_end() {
+        assert_eq!(kelvin_to_rgb(1000), [255, 68, 0]);
+        assert_eq!(temperature_strength(1000), 1.0);
+        assert!((temperature_strength(3750) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn temperatures_get_warmer_step_by_step() {
+        let colors: Vec<[u8; 3]> = (MIN_TEMPERATURE..=MAX_TEMPERATURE).step_by(100).map(kelvin_to_rgb).collect();
+        for pair in colors.windows(2) {
+            assert_eq!(pair[1][0], 255);
+            assert!(pair[0][1] <= pair[1][1] && pair[0][2] <= pair[1][2], "{:?}", pair);
+        }
+    }
+
+    #[test]
+    fn out_of_range_temperatures_are_clamped() {
+        assert_eq!(kelvin_to_rgb(0), kelvin_to_rgb(MIN_TEMPERATURE));
+        assert_eq!(kelvin_to_rgb(500), kelvin_to_rgb(MIN_TEMPERATURE));
+        assert_eq!(kelvin_to_rgb(20000), kelvin_to_rgb(MAX_TEMPERATURE));
+        assert_eq!(kelvin_to_rgb(u32::MAX), kelvin_to_rgb(MAX_TEMPERATURE));
+        assert_eq!(temperature_strength(0), 1.0);
+        assert_eq!(temperature_strength(20000), 0.0);
+        assert_eq!(clamp_temperature(7000), MAX_TEMPERATURE);
+    }
+
+    #[test]
+    fn channel_gains_of_no_tint_leave_the_screen_alone() {
+        let tint = Tint { rgb: [255, 0, 0], alpha: 0, dim: 0 };
+        assert_gains(tint.channel_gains(), [1.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn channel_gains_blend_the_color_over_white() {
+        // Opaque red lets only red through
+        assert_gains(Tint { rgb: [255, 0, 0], alpha: 255, dim: 0 }.channel_gains(), [1.0, 0.0, 0.0]);
+        // Half-transparent orange: a*C + (1 - a) per channel
+        let alpha = 128.0 / 255.0;
+        let green = 128.0 / 255.0;
+        assert_gains(
+            Tint { rgb: [255, 128, 0], alpha: 128, dim: 0 }.channel_gains(),
+            [1.0, alpha * green + 1.0 - alpha, 1.0 - alpha],
+        );
+    }
+
+    #[test]
+    fn channel_gains_dim_every_channel() {
+        let keep = 1.0 - 102.0 / 255.0;
+        assert_gains(Tint { rgb: [255, 0, 0], alpha: 0, dim: 102 }.channel_gains(), [keep, keep, keep]);
+        assert_gains(Tint { rgb: [255, 0, 0], alpha: 255, dim: 255 }.channel_gains(), [0.0, 0.0, 0.0]);
+    }
+}
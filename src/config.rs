@@ -0,0 +1,450 @@
+// Reading and writing config.json: schema versions, migrations from older layouts and
+// validation. A file we can't use is moved aside instead of being overwritten by defaults.
+//
+// Writes go to a temporary file that is then renamed over config.json, so the overlay (or an
+// editor) reading concurrently sees either the old or the new file, never half of one. Saves
+// from the UI are debounced: dragging a slider only writes once the value settles.
+
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Condvar, Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde_json::{Map, Value};
+use crate::color;
+use crate::overlay::OverlayConfig;
+use crate::profile;
+use crate::rules::RuleAction;
+
+// Bump when the layout changes, and add the step from the previous version to MIGRATIONS
+pub const CURRENT_VERSION: u32 = 8;
+
+// Quiet time after the last change before it is written
+const SAVE_DELAY: Duration = Duration::from_millis(400);
+// Virus scanners and indexers briefly hold files open on Windows, which fails the rename
+const RENAME_ATTEMPTS: u32 = 5;
+
+struct PendingSave {
+    path: PathBuf,
+    config: OverlayConfig,
+    due: Instant,
+}
+
+static PENDING: Mutex<Option<PendingSave>> = Mutex::new(None);
+static PENDING_CHANGED: Condvar = Condvar::new();
+// Serializes the actual writes between the save thread and `flush`
+static WRITING: Mutex<()> = Mutex::new(());
+static SAVE_THREAD: Once = Once::new();
+
+// MIGRATIONS[n] upgrades a version n file to version n + 1
+const MIGRATIONS: &[fn(&mut Map<String, Value>)] = &[
+    migrate_v0,
+    migrate_v1,
+    // 3: per-monitor settings
+    added_keys,
+    // 4: hotkeys
+    added_keys,
+    // 5: fullscreen handling
+    added_keys,
+    // 6: per-application rules
+    added_keys,
+    // 7: brightness, also in profiles
+    added_keys,
+    // 8: tint color, also in profiles and monitors
+    added_keys,
+];
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Io(io::Error),
+    // Not JSON, wrong types or unknown keys
+    Parse(String),
+    // Well-formed but with values we can't use
+    Invalid(Vec<String>),
+    // Written by a newer RedShift
+    TooNew(u32),
+}
+
+impl std::fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Io(e) => write!(f, "{}", e),
+            ConfigError::Parse(message) => write!(f, "{}", message),
+            ConfigError::Invalid(problems) => write!(f, "{}", problems.join("; ")),
+            ConfigError::TooNew(version) => write!(
+                f,
+                "config version {} is newer than this RedShift understands (version {})",
+                version, CURRENT_VERSION
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// Version 0 had no version key. Builds from back then accepted any opacity, while the
+// slider now only covers 90-200.
+fn migrate_v0(config: &mut Map<String, Value>) {
+    if let Some(opacity) = config.get("opacity").and_then(Value::as_u64) {
+        config.insert("opacity".to_owned(), Value::from(opacity.clamp(90, 200)));
+    }
+}
+
+// Version 2 added profiles; start upgraded configs with the stock ones
+fn migrate_v1(config: &mut Map<String, Value>) {
+    if !config.contains_key("profiles") {
+        if let Ok(profiles) = serde_json::to_value(profile::default_profiles()) {
+            config.insert("profiles".to_owned(), profiles);
+        }
+    }
+}
+
+// For versions that only added keys with defaults. The bump still matters: older builds reject
+// unknown keys, and a version they don't know gets a clearer error than an unknown key.
+fn added_keys(_config: &mut Map<String, Value>) {}
+
+// Ok(None) if there is no config file yet
+pub fn load(path: &Path) -> Result<Option<OverlayConfig>, ConfigError> {
+    match fs::read_to_string(path) {
+        Ok(text) => parse(&text).map(Some),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(ConfigError::Io(e)),
+    }
+}
+
+pub fn parse(text: &str) -> Result<OverlayConfig, ConfigError> {
+    let value: Value = serde_json::from_str(text).map_err(|e| ConfigError::Parse(e.to_string()))?;
+    let Value::Object(mut map) = value else {
+        return Err(ConfigError::Parse("expected a JSON object".to_owned()));
+    };
+
+    let version = match map.get("version") {
+        None => 0,
+        Some(version) => version
+            .as_u64()
+            .map(|version| version as u32)
+            .ok_or_else(|| ConfigError::Parse(format!("invalid version {}", version)))?,
+    };
+    if version > CURRENT_VERSION {
+        return Err(ConfigError::TooNew(version));
+    }
+    for (from, migrate) in MIGRATIONS.iter().enumerate().skip(version as usize) {
+        println!("Migrating config from version {} to {}", from, from + 1);
+        migrate(&mut map);
+    }
+    map.insert("version".to_owned(), Value::from(CURRENT_VERSION));
+
+    let config: OverlayConfig = serde_json::from_value(Value::Object(map))
+        .map_err(|e| ConfigError::Parse(e.to_string()))?;
+    validate(&config)?;
+    Ok(config)
+}
+
+fn validate(config: &OverlayConfig) -> Result<(), ConfigError> {
+    let mut problems = Vec::new();
+
+    if !(90..=200).contains(&config.opacity) {
+        problems.push(format!("opacity {} is out of range (90-200)", config.opacity));
+    }
+    if !(color::MIN_TEMPERATURE..=color::MAX_TEMPERATURE).contains(&config.color_temperature) {
+        problems.push(format!(
+            "color_temperature {}K is out of range ({}-{}K)",
+            config.color_temperature, color::MIN_TEMPERATURE, color::MAX_TEMPERATURE
+        ));
+    }
+    if !(color::MIN_BRIGHTNESS..=color::MAX_BRIGHTNESS).contains(&config.brightness) {
+        problems.push(format!(
+            "brightness {}% is out of range ({}-{}%)",
+            config.brightness, color::MIN_BRIGHTNESS, color::MAX_BRIGHTNESS
+        ));
+    }
+    if !(-90.0..=90.0).contains(&config.schedule.latitude) {
+        problems.push(format!("schedule.latitude {} is out of range (-90 to 90)", config.schedule.latitude));
+    }
+    if !(-180.0..=180.0).contains(&config.schedule.longitude) {
+        problems.push(format!("schedule.longitude {} is out of range (-180 to 180)", config.schedule.longitude));
+    }
+    if !(0.0..=60.0).contains(&config.fade.manual_seconds) {
+        problems.push(format!("fade.manual_seconds {} is out of range (0-60)", config.fade.manual_seconds));
+    }
+    if let Some(minutes) = config.fade.schedule_minutes {
+        if !(1.0..=240.0).contains(&minutes) {
+            problems.push(format!("fade.schedule_minutes {} is out of range (1-240)", minutes));
+        }
+    }
+
+    for (index, profile) in config.profiles.iter().enumerate() {
+        let name = profile.name.trim();
+        if name.is_empty() {
+            problems.push(format!("profile {} has an empty name", index + 1));
+        } else if config.profiles[..index].iter().any(|other| other.name.trim().eq_ignore_ascii_case(name)) {
+            problems.push(format!("profile name '{}' is used more than once", name));
+        }
+        if !(90..=200).contains(&profile.opacity) {
+            problems.push(format!("profile '{}': opacity {} is out of range (90-200)", name, profile.opacity));
+        }
+        if !(color::MIN_TEMPERATURE..=color::MAX_TEMPERATURE).contains(&profile.color_temperature) {
+            problems.push(format!(
+                "profile '{}': color_temperature {}K is out of range ({}-{}K)",
+                name, profile.color_temperature, color::MIN_TEMPERATURE, color::MAX_TEMPERATURE
+            ));
+        }
+        if !(color::MIN_BRIGHTNESS..=color::MAX_BRIGHTNESS).contains(&profile.brightness) {
+            problems.push(format!(
+                "profile '{}': brightness {}% is out of range ({}-{}%)",
+                name, profile.brightness, color::MIN_BRIGHTNESS, color::MAX_BRIGHTNESS
+            ));
+        }
+    }
+    for monitor in &config.monitors {
+        if let Some(opacity) = monitor.opacity {
+            if !(90..=200).contains(&opacity) {
+                problems.push(format!("monitor '{}': opacity {} is out of range (90-200)", monitor.name, opacity));
+            }
+        }
+        if let Some(temperature) = monitor.color_temperature {
+            if !(color::MIN_TEMPERATURE..=color::MAX_TEMPERATURE).contains(&temperature) {
+                problems.push(format!(
+                    "monitor '{}': color_temperature {}K is out of range ({}-{}K)",
+                    monitor.name, temperature, color::MIN_TEMPERATURE, color::MAX_TEMPERATURE
+                ));
+            }
+        }
+    }
+    if config.fullscreen.reduce_to > 100 {
+        problems.push(format!("fullscreen.reduce_to {} is out of range (0-100)", config.fullscreen.reduce_to));
+    }
+    // Rules with an empty pattern or a missing profile are skipped when evaluated, so a rule
+    // half-way through being edited or a deleted profile don't make the whole file invalid
+    for rule in &config.rules {
+        if let RuleAction::Opacity(opacity) = rule.action {
+            if !(90..=200).contains(&opacity) {
+                problems.push(format!("rule '{}': opacity {} is out of range (90-200)", rule.pattern, opacity));
+            }
+        }
+    }
+    if !(1..=24 * 60).contains(&config.hotkeys.pause_minutes) {
+        problems.push(format!("hotkeys.pause_minutes {} is out of range (1-1440)", config.hotkeys.pause_minutes));
+    }
+    if let Some(default) = &config.default_profile {
+        if profile::find(&config.profiles, default).is_none() {
+            problems.push(format!("default_profile '{}' is not one of the profiles", default));
+        }
+    }
+
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(ConfigError::Invalid(problems))
+    }
+}
+
+// Write now, replacing the file atomically
+pub fn save(path: &Path, config: &OverlayConfig) -> io::Result<()> {
+    let mut config = config.clone();
+    config.version = CURRENT_VERSION;
+    let config_str = serde_json::to_string_pretty(&config)?;
+
+    let _writing = WRITING.lock().unwrap();
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    let mut temp_path = path.as_os_str().to_owned();
+    temp_path.push(format!(".tmp-{}", std::process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    let result = write_and_sync(&temp_path, config_str.as_bytes()).and_then(|_| replace(&temp_path, path));
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn write_and_sync(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).open(path)?;
+    file.write_all(contents)?;
+    // Make sure the data is on disk before the rename makes it visible
+    file.sync_all()
+}
+
+fn replace(from: &Path, to: &Path) -> io::Result<()> {
+    let mut attempt = 1;
+    loop {
+        match fs::rename(from, to) {
+            Err(e) if e.kind() == io::ErrorKind::PermissionDenied && attempt < RENAME_ATTEMPTS => {
+                attempt += 1;
+                thread::sleep(Duration::from_millis(20 * attempt as u64));
+            }
+            result => return result,
+        }
+    }
+}
+
+// Save once things have been quiet for a moment; later calls replace earlier pending ones
+pub fn save_debounced(path: &Path, config: &OverlayConfig) {
+    SAVE_THREAD.call_once(|| {
+        thread::spawn(run_save_thread);
+    });
+
+    *PENDING.lock().unwrap() = Some(PendingSave {
+        path: path.to_path_buf(),
+        config: config.clone(),
+        due: Instant::now() + SAVE_DELAY,
+    });
+    PENDING_CHANGED.notify_one();
+}
+
+// Write any pending save right away, e.g. before exiting
+pub fn flush() {
+    let pending = PENDING.lock().unwrap().take();
+    if let Some(pending) = pending {
+        write_pending(pending);
+    }
+}
+
+fn run_save_thread() {
+    let mut pending = PENDING.lock().unwrap();
+    loop {
+        let due = match pending.as_ref() {
+            Some(save) => save.due,
+            None => {
+                pending = PENDING_CHANGED.wait(pending).unwrap();
+                continue;
+            }
+        };
+
+        let now = Instant::now();
+        if now < due {
+            pending = PENDING_CHANGED.wait_timeout(pending, due - now).unwrap().0;
+            continue;
+        }
+
+        if let Some(save) = pending.take() {
+            drop(pending);
+            write_pending(save);
+            pending = PENDING.lock().unwrap();
+        }
+    }
+}
+
+fn write_pending(pending: PendingSave) {
+    match save(&pending.path, &pending.config) {
+        Ok(_) => println!("Successfully saved config"),
+        Err(e) => eprintln!("Failed to save config: {}", e),
+    }
+}
+
+// $XDG_CONFIG_HOME, or ~/.config when it isn't set
+#[cfg(not(target_os = "windows"))]
+pub fn xdg_config_home() -> Option<PathBuf> {
+    std::env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|path| path.is_absolute())
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+}
+
+// Move a config we couldn't use out of the way, so saving defaults doesn't destroy it
+pub fn backup_unreadable(path: &Path) -> io::Result<PathBuf> {
+    let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+    let mut backup = path.as_os_str().to_owned();
+    backup.push(format!(".invalid-{}", stamp));
+    let backup = PathBuf::from(backup);
+    fs::rename(path, &backup)?;
+    Ok(backup)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn version_0_is_migrated_and_clamped() {
+        let config = parse(r#"{ "opacity": 20, "enabled": true }"#).unwrap();
+        assert_eq!(config.version, CURRENT_VERSION);
+        assert_eq!(config.opacity, 90);
+        assert!(config.enabled);
+        assert_eq!(config.profiles, profile::default_profiles());
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        let text = format!(r#"{{ "version": {}, "opacity": 120, "colour": "red" }}"#, CURRENT_VERSION);
+        assert!(matches!(parse(&text), Err(ConfigError::Parse(message)) if message.contains("colour")));
+    }
+
+    // Left to the hotkey code to report; one bad binding doesn't cost the rest of the settings
+    #[test]
+    fn bad_hotkeys_keep_the_config() {
+        let text = format!(
+            r#"{{ "version": {}, "opacity": 150, "hotkeys": {{ "bindings": [
+                {{ "action": "toggle", "keys": "Ctrl+Alt+Nope" }},
+                {{ "action": "pause", "keys": "Ctrl+Alt+S" }},
+                {{ "action": "cycle_profile", "keys": "ctrl+alt+s" }}
+            ] }} }}"#,
+            CURRENT_VERSION
+        );
+        let config = parse(&text).unwrap();
+        assert_eq!(config.opacity, 150);
+        assert_eq!(config.hotkeys.problems().len(), 2);
+    }
+
+    #[test]
+    fn out_of_range_values_are_reported() {
+        let text = format!(r#"{{ "version": {}, "opacity": 250, "color_temperature": 9000 }}"#, CURRENT_VERSION);
+        match parse(&text) {
+            Err(ConfigError::Invalid(problems)) => {
+                assert_eq!(problems.len(), 2, "{:?}", problems);
+                assert!(problems[0].contains("opacity 250"));
+                assert!(problems[1].contains("color_temperature 9000K"));
+            }
+            other => panic!("expected Invalid, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    // Readers running alongside writers must always see a complete file, old or new
+    #[test]
+    fn parallel_saves_and_loads_never_see_a_partial_file() {
+        let dir = std::env::temp_dir().join(format!("redshift-config-test-{}", std::process::id()));
+        let path = dir.join("config.json");
+        save(&path, &OverlayConfig::default()).unwrap();
+
+        let writers: Vec<_> = (0..4u8)
+            .map(|writer| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for step in 0..50u8 {
+                        let config = OverlayConfig { opacity: 90 + writer * 25 + step % 25, ..OverlayConfig::default() };
+                        save(&path, &config).unwrap();
+                    }
+                })
+            })
+            .collect();
+        let readers: Vec<_> = (0..4)
+            .map(|_| {
+                let path = path.clone();
+                thread::spawn(move || {
+                    for _ in 0..200 {
+                        match load(&path) {
+                            Ok(Some(config)) => assert!((90..=200).contains(&config.opacity)),
+                            Ok(None) => panic!("config.json disappeared during a save"),
+                            Err(e) => panic!("read a broken config: {}", e),
+                        }
+                    }
+                })
+            })
+            .collect();
+
+        for handle in writers.into_iter().chain(readers) {
+            handle.join().unwrap();
+        }
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn newer_versions_are_refused() {
+        let text = format!(r#"{{ "version": {} }}"#, CURRENT_VERSION + 1);
+        assert!(matches!(parse(&text), Err(ConfigError::TooNew(version)) if version == CURRENT_VERSION + 1));
+    }
+}
@@ -0,0 +1,699 @@
+// The controller's settings and everything that changes them. The settings window, the tray
+// and requests from other launches (the command line) all go through here so they stay in sync
+// with each other, config.json and the overlay process.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use chrono::{DateTime, Local};
+use serde::{Serialize, Deserialize};
+use crate::color;
+use crate::config;
+use crate::hotkey::{self, HotkeyAction, HotkeyError, KeyCombo};
+use crate::ipc;
+use crate::monitor::{self, MonitorSettings};
+use crate::overlay::{self, OverlayConfig, TintMode};
+use crate::profile::{self, Profile, ProfileError};
+use crate::rules::{self, AppRule, ForegroundApp, RuleEffect};
+use crate::schedule;
+use crate::supervisor::{self, OverlayHealth};
+use crate::tray;
+
+static CONFIG: Mutex<Option<OverlayConfig>> = Mutex::new(None);
+// Why the saved settings couldn't be used, until the user dismisses it
+static CONFIG_PROBLEM: Mutex<Option<String>> = Mutex::new(None);
+// When the current pause ends; the overlay keeps running meanwhile, just transparent
+static PAUSED_UNTIL: Mutex<Option<SystemTime>> = Mutex::new(None);
+
+// Last app other than RedShift in the foreground, and the pattern of the rule it matched
+static FOREGROUND: Mutex<Option<(ForegroundApp, Option<String>)>> = Mutex::new(None);
+
+const RULE_POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Quick pause lengths offered in the tray and the settings window
+pub const PAUSE_MINUTES: &[u32] = &[5, 15, 60];
+
+// How far one press of the intensity hotkeys moves the slider
+const OPACITY_STEP: u8 = 11;
+const TEMPERATURE_STEP: u32 = 300;
+const BRIGHTNESS_STEP: u8 = 10;
+
+// Canned settings for `redshift preset <name>`. Fields left as None are not touched.
+pub struct Preset {
+    pub name: &'static str,
+    pub description: &'static str,
+    enabled: bool,
+    tint_mode: Option<TintMode>,
+    opacity: Option<u8>,
+    color_temperature: Option<u32>,
+    tint_color: Option<[u8; 3]>,
+}
+
+pub const PRESETS: &[Preset] = &[
+    Preset {
+        name: "day",
+        description: "overlay off",
+        enabled: false,
+        tint_mode: None,
+        opacity: None,
+        color_temperature: None,
+        tint_color: None,
+    },
+    Preset {
+        name: "evening",
+        description: "mild warm tint, 4500K",
+        enabled: true,
+        tint_mode: Some(TintMode::Temperature),
+        opacity: None,
+        color_temperature: Some(4500),
+        tint_color: None,
+    },
+    Preset {
+        name: "night",
+        description: "strong warm tint, 2700K",
+        enabled: true,
+        tint_mode: Some(TintMode::Temperature),
+        opacity: Some(200),
+        color_temperature: Some(2700),
+        tint_color: None,
+    },
+    Preset {
+        name: "red",
+        description: "full red overlay",
+        enabled: true,
+        tint_mode: Some(TintMode::Intensity),
+        opacity: Some(200),
+        color_temperature: None,
+        tint_color: Some([255, 0, 0]),
+    },
+];
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ControllerStatus {
+    pub enabled: bool,
+    pub tint_mode: TintMode,
+    pub opacity: u8,
+    pub color_temperature: u32,
+    pub tint_color: [u8; 3],
+    pub brightness: u8,
+    // "stopped", "running", "restarting" or "failed"
+    pub overlay: String,
+    // Alpha the overlay last reported, after the schedule
+    pub overlay_alpha: Option<u8>,
+    pub problem: Option<String>,
+    // Profile the current settings match, if any
+    pub active_profile: Option<String>,
+    // Unix time the current pause ends
+    pub paused_until: Option<u64>,
+    // Pattern of the app rule in effect
+    pub app_rule: Option<String>,
+}
+
+#[derive(Debug)]
+pub struct PauseError(pub String);
+
+impl std::fmt::Display for PauseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for PauseError {}
+
+// Load the saved settings, switch to the default profile and bring the overlay up if it was left on
+pub fn init() -> OverlayConfig {
+    let mut config = load_config();
+    let default = config.default_profile.as_deref().and_then(|name| profile::find(&config.profiles, name)).cloned();
+    if let Some(default) = default {
+        println!("Starting with default profile: {}", default.name);
+        default.apply_to(&mut config);
+    }
+    *CONFIG.lock().unwrap() = Some(config.clone());
+    if config.enabled {
+        start_overlay(&config);
+    }
+    config
+}
+
+pub fn config_problem() -> Option<String> {
+    CONFIG_PROBLEM.lock().unwrap().clone()
+}
+
+pub fn dismiss_config_problem() {
+    *CONFIG_PROBLEM.lock().unwrap() = None;
+}
+
+pub fn config() -> OverlayConfig {
+    CONFIG.lock().unwrap().clone().unwrap_or_default()
+}
+
+// Change the settings and save them; returns the new settings
+pub fn update(change: impl FnOnce(&mut OverlayConfig)) -> OverlayConfig {
+    let mut slot = CONFIG.lock().unwrap();
+    let config = slot.get_or_insert_with(OverlayConfig::default);
+    change(config);
+    save_config(config);
+    supervisor::update_config(config);
+    config.clone()
+}
+
+fn start_overlay(config: &OverlayConfig) {
+    if let Err(e) = supervisor::start(config) {
+        eprintln!("Failed to start overlay process: {}", e);
+    }
+}
+
+fn stop_overlay() {
+    if let Some(overlay) = supervisor::stop() {
+        // Let it fade out in the background so the caller stays responsive
+        std::thread::spawn(move || overlay.shutdown());
+    }
+}
+
+// Push a command to the overlay; config.json is still saved, so a miss here only costs latency
+fn send_to_overlay(command: ipc::Command) {
+    if let Err(e) = supervisor::send(command) {
+        eprintln!("Failed to send command to overlay: {}", e);
+    }
+}
+
+fn send_color(config: &OverlayConfig) {
+    send_to_overlay(ipc::Command::SetColor {
+        tint_mode: config.tint_mode,
+        color_temperature: config.color_temperature,
+        tint_color: config.tint_color,
+    });
+}
+
+pub fn set_opacity(opacity: u8) {
+    let clamped_opacity = opacity.clamp(90, 200);
+    println!("Controller updating opacity to: {}", clamped_opacity);
+    update(|config| config.opacity = clamped_opacity);
+    send_to_overlay(ipc::Command::SetOpacity { opacity: clamped_opacity });
+}
+
+pub fn set_temperature(temperature: u32) {
+    let clamped_temperature = color::clamp_temperature(temperature);
+    println!("Controller updating color temperature to: {}K", clamped_temperature);
+    let config = update(|config| config.color_temperature = clamped_temperature);
+    send_color(&config);
+}
+
+// Sent on every change, so dragging in the color picker previews live
+pub fn set_tint_color(tint_color: [u8; 3]) {
+    println!("Controller updating tint color to: {}", color::to_hex(tint_color));
+    let config = update(|config| config.tint_color = tint_color);
+    send_color(&config);
+}
+
+pub fn set_brightness(brightness: u8) {
+    let clamped_brightness = color::clamp_brightness(brightness);
+    println!("Controller updating brightness to: {}%", clamped_brightness);
+    update(|config| config.brightness = clamped_brightness);
+    send_to_overlay(ipc::Command::SetBrightness { brightness: clamped_brightness });
+}
+
+pub fn set_tint_mode(mode: TintMode) {
+    println!("Controller switching tint mode to: {:?}", mode);
+    let config = update(|config| config.tint_mode = mode);
+    send_color(&config);
+}
+
+pub fn set_enabled(enabled: bool) {
+    let was_enabled = config().enabled;
+    let config = update(|config| config.enabled = enabled);
+    if enabled == was_enabled {
+        return;
+    }
+    // Turning the overlay on or off by hand ends any pause. Forget it while no overlay is
+    // running, so a fading-out overlay isn't told to resume.
+    if enabled {
+        forget_pause();
+        start_overlay(&config);
+    } else {
+        stop_overlay();
+        forget_pause();
+    }
+}
+
+// Returns whether the overlay is now enabled
+pub fn toggle() -> bool {
+    let enabled = !config().enabled;
+    set_enabled(enabled);
+    enabled
+}
+
+pub fn find_preset(name: &str) -> Option<&'static Preset> {
+    PRESETS.iter().find(|preset| preset.name.eq_ignore_ascii_case(name))
+}
+
+pub fn apply_preset(preset: &Preset) {
+    println!("Controller applying preset: {} ({})", preset.name, preset.description);
+    let config = update(|config| {
+        if let Some(mode) = preset.tint_mode {
+            config.tint_mode = mode;
+        }
+        if let Some(temperature) = preset.color_temperature {
+            config.color_temperature = color::clamp_temperature(temperature);
+        }
+        if let Some(tint_color) = preset.tint_color {
+            config.tint_color = tint_color;
+        }
+        if let Some(opacity) = preset.opacity {
+            config.opacity = opacity.clamp(90, 200);
+        }
+    });
+    send_color(&config);
+    send_to_overlay(ipc::Command::SetOpacity { opacity: config.opacity });
+    set_enabled(preset.enabled);
+}
+
+pub fn status() -> ControllerStatus {
+    let config = config();
+    let (overlay, problem) = match supervisor::health() {
+        OverlayHealth::Stopped => ("stopped", None),
+        OverlayHealth::Running => ("running", None),
+        OverlayHealth::Restarting { reason, .. } => ("restarting", Some(reason)),
+        OverlayHealth::Failed { reason } => ("failed", Some(reason)),
+    };
+    let overlay_alpha = supervisor::with_process(|process| {
+        process.and_then(|process| process.link()).and_then(|link| link.status()).map(|status| status.opacity)
+    });
+
+    ControllerStatus {
+        enabled: config.enabled,
+        tint_mode: config.tint_mode,
+        opacity: config.opacity,
+        color_temperature: config.color_temperature,
+        tint_color: config.tint_color,
+        brightness: config.brightness,
+        overlay: overlay.to_owned(),
+        overlay_alpha,
+        problem,
+        active_profile: active_profile(&config),
+        paused_until: paused_until()
+            .and_then(|until| until.duration_since(UNIX_EPOCH).ok())
+            .map(|since_epoch| since_epoch.as_secs()),
+        app_rule: foreground().and_then(|(_, rule)| rule),
+    }
+}
+
+// Monitors for the settings window: the ones the overlay is drawing on, with their saved
+// settings, then saved ones that aren't connected right now. The flag says connected.
+pub fn monitors() -> Vec<(MonitorSettings, bool)> {
+    let config = config();
+    let outputs = supervisor::with_process(|process| {
+        process.and_then(|process| process.link()).and_then(|link| link.status()).map(|status| status.outputs)
+    }).unwrap_or_default();
+
+    let mut monitors: Vec<(MonitorSettings, bool)> = outputs
+        .iter()
+        .map(|output| {
+            let mut settings = monitor::find(&config.monitors, output).cloned().unwrap_or_else(|| MonitorSettings::new(output));
+            // A name-only match follows the monitor to where it is now
+            settings.position = output.position;
+            settings.size = output.size;
+            (settings, true)
+        })
+        .collect();
+    for saved in &config.monitors {
+        let shown = outputs.iter().any(|output| monitor::find(&config.monitors, output) == Some(saved));
+        if !shown {
+            monitors.push((saved.clone(), false));
+        }
+    }
+    monitors
+}
+
+// Store one monitor's settings and pass the whole list on to the overlay
+pub fn set_monitor(settings: MonitorSettings) {
+    println!("Controller updating monitor {}: {:?}", settings.name, settings);
+    let config = update(|config| {
+        match monitor::index_of(&config.monitors, &settings.name, settings.position, settings.size) {
+            Some(index) => config.monitors[index] = settings,
+            None => config.monitors.push(settings),
+        }
+        // Entries that only repeat the global settings aren't worth keeping
+        config.monitors.retain(|monitor| !monitor.is_default());
+    });
+    send_to_overlay(ipc::Command::SetMonitors { monitors: config.monitors });
+}
+
+pub fn active_profile(config: &OverlayConfig) -> Option<String> {
+    config.profiles.iter().find(|profile| profile.matches(config)).map(|profile| profile.name.clone())
+}
+
+fn unknown_profile(name: &str) -> ProfileError {
+    ProfileError(format!("no profile named '{}'", name))
+}
+
+pub fn use_profile(name: &str) -> Result<(), ProfileError> {
+    let config = config();
+    let profile = profile::find(&config.profiles, name).cloned().ok_or_else(|| unknown_profile(name))?;
+    println!("Controller switching to profile: {}", profile.name);
+    let config = update(|config| profile.apply_to(config));
+    send_color(&config);
+    send_to_overlay(ipc::Command::SetOpacity { opacity: config.opacity });
+    send_to_overlay(ipc::Command::SetBrightness { brightness: config.brightness });
+    Ok(())
+}
+
+// New profile from the current settings
+pub fn create_profile(name: &str) -> Result<String, ProfileError> {
+    let name = profile::check_name(&config().profiles, name)?;
+    println!("Controller creating profile: {}", name);
+    update(|config| {
+        let profile = Profile::from_config(&name, config);
+        config.profiles.push(profile);
+    });
+    Ok(name)
+}
+
+// Overwrite a profile with the current settings
+pub fn save_profile(name: &str) -> Result<(), ProfileError> {
+    let mut result = Err(unknown_profile(name));
+    update(|config| {
+        let snapshot = config.clone();
+        if let Some(profile) = config.profiles.iter_mut().find(|profile| profile.name.eq_ignore_ascii_case(name)) {
+            *profile = Profile::from_config(&profile.name, &snapshot);
+            result = Ok(());
+        }
+    });
+    result
+}
+
+pub fn rename_profile(from: &str, to: &str) -> Result<String, ProfileError> {
+    let config = config();
+    let old_name = profile::find(&config.profiles, from).ok_or_else(|| unknown_profile(from))?.name.clone();
+    let others: Vec<Profile> = config.profiles.iter().filter(|profile| profile.name != old_name).cloned().collect();
+    let new_name = profile::check_name(&others, to)?;
+
+    println!("Controller renaming profile: {} -> {}", old_name, new_name);
+    update(|config| {
+        for profile in config.profiles.iter_mut().filter(|profile| profile.name == old_name) {
+            profile.name = new_name.clone();
+        }
+        if config.default_profile.as_deref() == Some(old_name.as_str()) {
+            config.default_profile = Some(new_name.clone());
+        }
+        for rule in &mut config.rules {
+            if let rules::RuleAction::Profile(name) = &mut rule.action {
+                if *name == old_name {
+                    *name = new_name.clone();
+                }
+            }
+        }
+    });
+    Ok(new_name)
+}
+
+pub fn delete_profile(name: &str) -> Result<(), ProfileError> {
+    let config = config();
+    let old_name = profile::find(&config.profiles, name).ok_or_else(|| unknown_profile(name))?.name.clone();
+    println!("Controller deleting profile: {}", old_name);
+    update(|config| {
+        config.profiles.retain(|profile| profile.name != old_name);
+        if config.default_profile.as_deref() == Some(old_name.as_str()) {
+            config.default_profile = None;
+        }
+    });
+    Ok(())
+}
+
+// None clears the default
+pub fn set_default_profile(name: Option<&str>) -> Result<(), ProfileError> {
+    let name = match name {
+        Some(name) => {
+            let config = config();
+            Some(profile::find(&config.profiles, name).ok_or_else(|| unknown_profile(name))?.name.clone())
+        }
+        None => None,
+    };
+    println!("Controller setting default profile: {:?}", name);
+    update(|config| config.default_profile = name);
+    Ok(())
+}
+
+pub fn paused_until() -> Option<SystemTime> {
+    *PAUSED_UNTIL.lock().unwrap()
+}
+
+// Hide the tint until `until` without stopping the overlay; a newer pause replaces this one
+pub fn pause_until(until: SystemTime) -> Result<(), PauseError> {
+    if !config().enabled {
+        return Err(PauseError("the overlay is off".to_owned()));
+    }
+    println!("Controller pausing overlay until {}", format_clock(until));
+    *PAUSED_UNTIL.lock().unwrap() = Some(until);
+    if let Err(e) = supervisor::set_paused(true) {
+        eprintln!("Failed to pause overlay: {}", e);
+    }
+    update_tooltip();
+
+    // Compare against the wall clock rather than sleeping the whole duration, so time spent
+    // in standby counts too
+    thread::spawn(move || loop {
+        thread::sleep(Duration::from_secs(1));
+        if paused_until() != Some(until) {
+            return;
+        }
+        if SystemTime::now() >= until {
+            resume();
+            return;
+        }
+        update_tooltip();
+    });
+    Ok(())
+}
+
+pub fn pause_for(duration: Duration) -> Result<SystemTime, PauseError> {
+    let until = SystemTime::now() + duration;
+    pause_until(until)?;
+    Ok(until)
+}
+
+// Uses the schedule's coordinates, even while the schedule itself is turned off
+pub fn pause_until_sunrise() -> Result<SystemTime, PauseError> {
+    let schedule = config().schedule;
+    if schedule.latitude == 0.0 && schedule.longitude == 0.0 {
+        return Err(PauseError("set your coordinates under \"schedule\" in config.json first".to_owned()));
+    }
+    let sunrise = schedule::next_sunrise(&schedule)
+        .ok_or_else(|| PauseError("the sun doesn't rise within a year here".to_owned()))?;
+    let until = UNIX_EPOCH + Duration::from_secs_f64(sunrise);
+    pause_until(until)?;
+    Ok(until)
+}
+
+pub fn resume() {
+    if PAUSED_UNTIL.lock().unwrap().take().is_none() {
+        return;
+    }
+    println!("Controller resuming overlay");
+    if let Err(e) = supervisor::set_paused(false) {
+        eprintln!("Failed to resume overlay: {}", e);
+    }
+    update_tooltip();
+}
+
+// Drop a pause without telling the overlay, for when it is being started or stopped anyway
+fn forget_pause() {
+    if PAUSED_UNTIL.lock().unwrap().take().is_some() {
+        let _ = supervisor::set_paused(false);
+        update_tooltip();
+    }
+}
+
+// Countdown while paused, otherwise whatever the supervisor has to say
+fn update_tooltip() {
+    let text = match paused_until() {
+        Some(until) => format!(
+            "RedShift: paused until {}\nResumes in {}",
+            format_clock(until),
+            format_remaining(until.duration_since(SystemTime::now()).unwrap_or_default())
+        ),
+        None => supervisor::health().tooltip(),
+    };
+    tray::set_tooltip(&text);
+}
+
+// Local wall-clock time, e.g. "06:42"
+pub fn format_clock(time: SystemTime) -> String {
+    DateTime::<Local>::from(time).format("%H:%M").to_string()
+}
+
+// "1:05:09" or "4:59"
+pub fn format_remaining(remaining: Duration) -> String {
+    let seconds = remaining.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+pub fn set_rules(rules: Vec<AppRule>) {
+    println!("Controller updating app rules ({} rules)", rules.len());
+    update(|config| config.rules = rules);
+}
+
+pub fn foreground() -> Option<(ForegroundApp, Option<String>)> {
+    FOREGROUND.lock().unwrap().clone()
+}
+
+// Follow the foreground window and tell the overlay what the matching app rule does. Runs
+// the rules again on every poll, so edits and profile changes apply right away, but only
+// talks to the overlay when the outcome changes or a new overlay process came up.
+pub fn watch_app_rules() {
+    thread::spawn(|| {
+        let mut sent: Option<(u32, Option<RuleEffect>)> = None;
+        loop {
+            thread::sleep(RULE_POLL_INTERVAL);
+            let config = config();
+
+            // RedShift's own window keeps the previous app, so the rule doesn't flip while
+            // the settings window has the focus
+            let app = rules::foreground_app().or_else(|| foreground().map(|(app, _)| app));
+            let Some(app) = app else { continue };
+            let matched = rules::evaluate(&config.rules, &config.profiles, &app);
+            let effect = matched.map(|(_, effect)| effect);
+            let pattern = matched.map(|(rule, _)| rule.pattern.clone());
+            let previous = FOREGROUND.lock().unwrap().replace((app.clone(), pattern.clone()));
+            if previous.and_then(|(_, rule)| rule) != pattern {
+                println!("App rule for {}: {:?}", app.executable, pattern);
+            }
+
+            let overlay = supervisor::with_process(|process| {
+                process.filter(|process| process.link().is_some_and(|link| link.is_connected())).map(|process| process.pid())
+            });
+            let Some(pid) = overlay else {
+                sent = None;
+                continue;
+            };
+            if sent != Some((pid, effect)) {
+                match supervisor::send(ipc::Command::SetRuleEffect { effect }) {
+                    Ok(_) => sent = Some((pid, effect)),
+                    Err(e) => eprintln!("Failed to send app rule to overlay: {}", e),
+                }
+            }
+        }
+    });
+}
+
+// Bind an action to a key combination, or unbind it with None
+pub fn set_hotkey(action: HotkeyAction, keys: Option<&str>) -> Result<(), HotkeyError> {
+    let keys = keys.map(|keys| keys.parse::<KeyCombo>()).transpose()?;
+    if let Some(combo) = &keys {
+        let taken = config().hotkeys.bindings.iter().find(|binding| {
+            binding.action != action && binding.keys.parse::<KeyCombo>().is_ok_and(|bound| bound == *combo)
+        }).map(|binding| binding.action);
+        if let Some(other) = taken {
+            return Err(HotkeyError(format!("{} is already used for '{}'", combo, other.label())));
+        }
+    }
+
+    println!("Controller binding {:?} to {:?}", action, keys.as_ref().map(KeyCombo::to_string));
+    let config = update(|config| {
+        config.hotkeys.bindings.retain(|binding| binding.action != action);
+        if let Some(combo) = keys {
+            config.hotkeys.bindings.push(hotkey::Binding { action, keys: combo.to_string() });
+        }
+    });
+    hotkey::reload(&config.hotkeys);
+    Ok(())
+}
+
+pub fn run_hotkey(action: HotkeyAction) {
+    let config = config();
+    match action {
+        HotkeyAction::Toggle => {
+            toggle();
+        }
+        // Stronger means more opacity, or a warmer (lower) temperature
+        HotkeyAction::IntensityUp | HotkeyAction::IntensityDown => {
+            let stronger = action == HotkeyAction::IntensityUp;
+            match config.tint_mode {
+                TintMode::Intensity => {
+                    let opacity = if stronger {
+                        config.opacity.saturating_add(OPACITY_STEP)
+                    } else {
+                        config.opacity.saturating_sub(OPACITY_STEP)
+                    };
+                    set_opacity(opacity);
+                }
+                TintMode::Temperature => {
+                    let temperature = if stronger {
+                        config.color_temperature.saturating_sub(TEMPERATURE_STEP)
+                    } else {
+                        config.color_temperature + TEMPERATURE_STEP
+                    };
+                    set_temperature(temperature);
+                }
+            }
+        }
+        HotkeyAction::BrightnessUp => set_brightness(config.brightness.saturating_add(BRIGHTNESS_STEP)),
+        HotkeyAction::BrightnessDown => set_brightness(config.brightness.saturating_sub(BRIGHTNESS_STEP)),
+        HotkeyAction::CycleProfile => {
+            let active = active_profile(&config);
+            let index = active
+                .and_then(|name| config.profiles.iter().position(|profile| profile.name == name))
+                .map_or(0, |index| index + 1);
+            if let Some(next) = config.profiles.get(index).or(config.profiles.first()) {
+                if let Err(e) = use_profile(&next.name) {
+                    eprintln!("Failed to switch profile: {}", e);
+                }
+            }
+        }
+        HotkeyAction::Pause => {
+            if paused_until().is_some() {
+                resume();
+            } else if let Err(e) = pause_for(Duration::from_secs(config.hotkeys.pause_minutes as u64 * 60)) {
+                eprintln!("Failed to pause: {}", e);
+            }
+        }
+    }
+}
+
+// Stop our overlay (letting it fade out first) and exit the controller
+pub fn quit() -> ! {
+    config::flush();
+    if let Some(overlay) = supervisor::stop() {
+        overlay.shutdown();
+    }
+    std::process::exit(0);
+}
+
+// Debounced, so slider drags don't write on every step
+fn save_config(config: &OverlayConfig) {
+    config::save_debounced(&overlay::config_path(), config);
+}
+
+// Missing file means defaults. A file we can't use is moved aside, so the user's settings
+// aren't lost when we save over it, and the problem is shown in the settings window.
+fn load_config() -> OverlayConfig {
+    let config_path = overlay::config_path();
+    println!("Loading config from: {}", config_path.display());
+
+    let error = match config::load(&config_path) {
+        Ok(Some(config)) => return config,
+        Ok(None) => {
+            println!("No config file yet, using defaults");
+            return OverlayConfig::default();
+        }
+        Err(e) => e,
+    };
+
+    eprintln!("Failed to load config: {}", error);
+    let problem = match config::backup_unreadable(&config_path) {
+        Ok(backup) => {
+            eprintln!("Moved unreadable config to {}", backup.display());
+            format!("Settings were reset: {}\nThe old file was kept as {}", error, backup.display())
+        }
+        Err(e) => {
+            eprintln!("Failed to back up unreadable config: {}", e);
+            format!("Settings were reset: {}", error)
+        }
+    };
+    *CONFIG_PROBLEM.lock().unwrap() = Some(problem);
+    OverlayConfig::default()
+}
@@ -0,0 +1,114 @@
+// Fade transitions between tints, interpolated by the overlay render loop
+
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use crate::color::Tint;
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Easing {
+    Linear,
+    // Slow start and end (smoothstep), the least noticeable for long transitions
+    #[default]
+    EaseInOut,
+    // Fast start, slow end
+    EaseOut,
+    // Slow start, fast end
+    EaseIn,
+}
+
+impl Easing {
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+        match self {
+            Easing::Linear => t,
+            Easing::EaseInOut => t * t * (3.0 - 2.0 * t),
+            Easing::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Easing::EaseIn => t * t,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FadeConfig {
+    // Fade for manual changes and for starting/stopping the overlay
+    #[serde(default = "default_manual_seconds")]
+    pub manual_seconds: f32,
+    // Length of the scheduled dusk/dawn transition, civil twilight when unset
+    #[serde(default)]
+    pub schedule_minutes: Option<f32>,
+    #[serde(default)]
+    pub easing: Easing,
+    // Curve for scheduled dimming, `easing` when unset. `ease_in` keeps the screen bright
+    // through most of dusk and dims towards the end.
+    #[serde(default)]
+    pub brightness_easing: Option<Easing>,
+}
+
+impl Default for FadeConfig {
+    fn default() -> Self {
+        Self {
+            manual_seconds: default_manual_seconds(),
+            schedule_minutes: None,
+            easing: Easing::default(),
+            brightness_easing: None,
+        }
+    }
+}
+
+fn default_manual_seconds() -> f32 {
+    0.6
+}
+
+impl FadeConfig {
+    pub fn manual_duration(&self) -> Duration {
+        Duration::from_secs_f32(self.manual_seconds.max(0.0))
+    }
+}
+
+pub struct Fade {
+    from: Tint,
+    to: Tint,
+    start: Instant,
+    duration: Duration,
+    easing: Easing,
+}
+
+impl Fade {
+    // A fade that has already settled on `tint`
+    pub fn new(tint: Tint) -> Self {
+        Self {
+            from: tint,
+            to: tint,
+            start: Instant::now(),
+            duration: Duration::ZERO,
+            easing: Easing::Linear,
+        }
+    }
+
+    pub fn target(&self) -> Tint {
+        self.to
+    }
+
+    // Start fading from wherever we are right now towards `to`
+    pub fn retarget(&mut self, to: Tint, duration: Duration, easing: Easing, now: Instant) {
+        self.from = self.value(now);
+        self.to = to;
+        self.start = now;
+        self.duration = duration;
+        self.easing = easing;
+    }
+
+    pub fn value(&self, now: Instant) -> Tint {
+        if self.duration.is_zero() {
+            return self.to;
+        }
+        let progress = now.saturating_duration_since(self.start).as_secs_f32() / self.duration.as_secs_f32();
+        self.from.lerp(&self.to, self.easing.apply(progress))
+    }
+
+    pub fn is_running(&self, now: Instant) -> bool {
+        now.saturating_duration_since(self.start) < self.duration
+    }
+}
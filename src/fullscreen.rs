@@ -0,0 +1,49 @@
+// What the overlay does on a monitor while a game or video player fills it completely.
+// Detection is up to the backend; see TintBackend::fullscreen_outputs.
+
+use serde::{Serialize, Deserialize};
+
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FullscreenAction {
+    // Keep tinting as usual
+    #[default]
+    Ignore,
+    // Fade the tint out on that monitor
+    Suspend,
+    // Tint that monitor at `reduce_to` percent of the usual strength
+    Reduce,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct FullscreenConfig {
+    #[serde(default)]
+    pub action: FullscreenAction,
+    #[serde(default = "default_reduce_to")]
+    pub reduce_to: u8,
+}
+
+impl Default for FullscreenConfig {
+    fn default() -> Self {
+        Self {
+            action: FullscreenAction::default(),
+            reduce_to: default_reduce_to(),
+        }
+    }
+}
+
+fn default_reduce_to() -> u8 {
+    50
+}
+
+impl FullscreenConfig {
+    // Alpha for a monitor that shows a fullscreen window
+    pub fn scale(&self, alpha: u8) -> u8 {
+        match self.action {
+            FullscreenAction::Ignore => alpha,
+            FullscreenAction::Suspend => 0,
+            FullscreenAction::Reduce => (alpha as u16 * self.reduce_to.min(100) as u16 / 100) as u8,
+        }
+    }
+}
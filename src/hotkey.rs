@@ -0,0 +1,478 @@
+// Global hotkeys: key combinations that work while another application has the focus.
+//
+// Bindings are stored as text ("Ctrl+Alt+R") so they can be edited in config.json. On Windows
+// they are registered with RegisterHotKey from a thread of their own, which receives WM_HOTKEY
+// in its message queue; re-registering after a change is requested by posting that thread a
+// message, since hotkeys can only be unregistered by the thread that registered them.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use serde::{Serialize, Deserialize};
+
+#[cfg(target_os = "windows")]
+use std::sync::atomic::{AtomicU32, Ordering};
+#[cfg(target_os = "windows")]
+use once_cell::sync::OnceCell;
+#[cfg(target_os = "windows")]
+use winapi::{
+    um::processthreadsapi::GetCurrentThreadId,
+    um::winuser::{
+        GetMessageW, PeekMessageW, PostThreadMessageW, RegisterHotKey, UnregisterHotKey, MSG,
+        MOD_ALT, MOD_CONTROL, MOD_NOREPEAT, MOD_SHIFT, MOD_WIN, PM_NOREMOVE, WM_APP, WM_HOTKEY,
+    },
+};
+
+// Posted to the hotkey thread to make it register the pending bindings
+#[cfg(target_os = "windows")]
+const WM_RELOAD_HOTKEYS: u32 = WM_APP + 1;
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum HotkeyAction {
+    Toggle,
+    IntensityUp,
+    IntensityDown,
+    CycleProfile,
+    Pause,
+    BrightnessUp,
+    BrightnessDown,
+}
+
+pub const ACTIONS: &[HotkeyAction] = &[
+    HotkeyAction::Toggle,
+    HotkeyAction::IntensityUp,
+    HotkeyAction::IntensityDown,
+    HotkeyAction::BrightnessUp,
+    HotkeyAction::BrightnessDown,
+    HotkeyAction::CycleProfile,
+    HotkeyAction::Pause,
+];
+
+impl HotkeyAction {
+    pub fn label(self) -> &'static str {
+        match self {
+            HotkeyAction::Toggle => "Toggle overlay",
+            HotkeyAction::IntensityUp => "Stronger",
+            HotkeyAction::IntensityDown => "Weaker",
+            HotkeyAction::CycleProfile => "Next profile",
+            HotkeyAction::Pause => "Pause",
+            HotkeyAction::BrightnessUp => "Brighter",
+            HotkeyAction::BrightnessDown => "Dimmer",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Binding {
+    pub action: HotkeyAction,
+    pub keys: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct HotkeyConfig {
+    #[serde(default = "default_bindings")]
+    pub bindings: Vec<Binding>,
+    // How long the pause hotkey pauses for
+    #[serde(default = "default_pause_minutes")]
+    pub pause_minutes: u32,
+}
+
+impl Default for HotkeyConfig {
+    fn default() -> Self {
+        Self {
+            bindings: default_bindings(),
+            pause_minutes: default_pause_minutes(),
+        }
+    }
+}
+
+// Ctrl+Alt+arrow keys rotate the screen with some graphics drivers, so intensity uses Page Up/Down
+fn default_bindings() -> Vec<Binding> {
+    [
+        (HotkeyAction::Toggle, "Ctrl+Alt+R"),
+        (HotkeyAction::IntensityUp, "Ctrl+Alt+PageUp"),
+        (HotkeyAction::IntensityDown, "Ctrl+Alt+PageDown"),
+        (HotkeyAction::BrightnessUp, "Ctrl+Alt+Home"),
+        (HotkeyAction::BrightnessDown, "Ctrl+Alt+End"),
+        (HotkeyAction::CycleProfile, "Ctrl+Alt+P"),
+        (HotkeyAction::Pause, "Ctrl+Alt+S"),
+    ]
+    .into_iter()
+    .map(|(action, keys)| Binding { action, keys: keys.to_owned() })
+    .collect()
+}
+
+fn default_pause_minutes() -> u32 {
+    15
+}
+
+impl HotkeyConfig {
+    pub fn binding(&self, action: HotkeyAction) -> Option<&str> {
+        self.bindings.iter().find(|binding| binding.action == action).map(|binding| binding.keys.as_str())
+    }
+
+    // Bindings that can't be registered: keys that don't parse, and combinations already bound
+    // to an earlier action. They are reported like registration failures and the rest still work.
+    pub fn problems(&self) -> Vec<(HotkeyAction, String)> {
+        let mut problems = Vec::new();
+        let mut seen: Vec<(KeyCombo, HotkeyAction)> = Vec::new();
+        for binding in &self.bindings {
+            match binding.keys.parse::<KeyCombo>() {
+                Ok(combo) => {
+                    if let Some((_, other)) = seen.iter().find(|(known, _)| *known == combo) {
+                        problems.push((binding.action, format!("{} is already bound to '{}'", combo, other.label())));
+                        continue;
+                    }
+                    seen.push((combo, binding.action));
+                }
+                Err(e) => problems.push((binding.action, e.to_string())),
+            }
+        }
+        problems
+    }
+}
+
+#[derive(Debug)]
+pub struct HotkeyError(pub String);
+
+impl fmt::Display for HotkeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for HotkeyError {}
+
+// A parsed binding. Keys are named the way they are written in the config: "A"-"Z", "0"-"9",
+// "F1"-"F24", arrows as "Up"/"Down"/"Left"/"Right", and "PageUp", "PageDown", "Home", "End",
+// "Insert", "Delete", "Space", "Minus", "Plus".
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct KeyCombo {
+    pub ctrl: bool,
+    pub alt: bool,
+    pub shift: bool,
+    pub win: bool,
+    pub key: String,
+}
+
+const NAMED_KEYS: &[&str] = &[
+    "Up", "Down", "Left", "Right", "PageUp", "PageDown", "Home", "End", "Insert", "Delete", "Space",
+    "Minus", "Plus",
+];
+
+// Canonical spelling of a key name, if it's one we can bind
+fn canonical_key(name: &str) -> Option<String> {
+    if let Some(named) = NAMED_KEYS.iter().find(|named| named.eq_ignore_ascii_case(name)) {
+        return Some((*named).to_owned());
+    }
+    let mut chars = name.chars();
+    if let (Some(c), None) = (chars.next(), chars.next()) {
+        if c.is_ascii_alphanumeric() {
+            return Some(c.to_ascii_uppercase().to_string());
+        }
+    }
+    let function = name.strip_prefix('F').or_else(|| name.strip_prefix('f'))?;
+    match function.parse::<u32>() {
+        Ok(number) if (1..=24).contains(&number) => Some(format!("F{}", number)),
+        _ => None,
+    }
+}
+
+impl FromStr for KeyCombo {
+    type Err = HotkeyError;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let mut combo = KeyCombo { ctrl: false, alt: false, shift: false, win: false, key: String::new() };
+        let parts: Vec<&str> = text.split('+').map(str::trim).collect();
+        let (key, modifiers) = parts.split_last().filter(|(key, _)| !key.is_empty())
+            .ok_or_else(|| HotkeyError(format!("'{}' has no key", text)))?;
+
+        for modifier in modifiers {
+            let held = match modifier.to_ascii_lowercase().as_str() {
+                "ctrl" | "control" => &mut combo.ctrl,
+                "alt" => &mut combo.alt,
+                "shift" => &mut combo.shift,
+                "win" | "super" => &mut combo.win,
+                _ => return Err(HotkeyError(format!("unknown modifier '{}' in '{}'", modifier, text))),
+            };
+            // Most likely a typo for another modifier
+            if *held {
+                return Err(HotkeyError(format!("'{}' is repeated in '{}'", modifier, text)));
+            }
+            *held = true;
+        }
+        combo.key = canonical_key(key).ok_or_else(|| HotkeyError(format!("unknown key '{}' in '{}'", key, text)))?;
+
+        // Shift alone would take the key away from typing
+        if !(combo.ctrl || combo.alt || combo.win) {
+            return Err(HotkeyError(format!("'{}' needs Ctrl, Alt or Win", text)));
+        }
+        Ok(combo)
+    }
+}
+
+impl fmt::Display for KeyCombo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (held, name) in [(self.ctrl, "Ctrl"), (self.alt, "Alt"), (self.shift, "Shift"), (self.win, "Win")] {
+            if held {
+                write!(f, "{}+", name)?;
+            }
+        }
+        write!(f, "{}", self.key)
+    }
+}
+
+// Key name for a key pressed in the settings window, in the spelling KeyCombo uses
+pub fn egui_key_name(key: eframe::egui::Key) -> Option<String> {
+    use eframe::egui::Key;
+    let name = match key {
+        Key::ArrowUp => "Up",
+        Key::ArrowDown => "Down",
+        Key::ArrowLeft => "Left",
+        Key::ArrowRight => "Right",
+        Key::PageUp => "PageUp",
+        Key::PageDown => "PageDown",
+        Key::Home => "Home",
+        Key::End => "End",
+        Key::Insert => "Insert",
+        Key::Delete => "Delete",
+        Key::Space => "Space",
+        Key::Minus => "Minus",
+        Key::PlusEquals => "Plus",
+        // Letters, digits and function keys are debug-printed as "A", "Num1" and "F1"
+        _ => {
+            let name = format!("{:?}", key);
+            return canonical_key(name.strip_prefix("Num").unwrap_or(&name));
+        }
+    };
+    Some(name.to_owned())
+}
+
+// Bindings that the system refused, with the reason; usually another program owns the keys
+static FAILURES: Mutex<Vec<(HotkeyAction, String)>> = Mutex::new(Vec::new());
+// Bindings waiting to be registered by the hotkey thread
+#[cfg(target_os = "windows")]
+static PENDING: Mutex<Option<HotkeyConfig>> = Mutex::new(None);
+#[cfg(target_os = "windows")]
+static THREAD_ID: AtomicU32 = AtomicU32::new(0);
+#[cfg(target_os = "windows")]
+static HANDLER: OnceCell<Box<dyn Fn(HotkeyAction) + Send + Sync>> = OnceCell::new();
+
+pub fn supported() -> bool {
+    cfg!(target_os = "windows")
+}
+
+pub fn failures() -> Vec<(HotkeyAction, String)> {
+    FAILURES.lock().unwrap().clone()
+}
+
+// Only Windows has global hotkeys so far
+#[cfg(not(target_os = "windows"))]
+pub fn start(config: &HotkeyConfig, _handler: impl Fn(HotkeyAction) + Send + Sync + 'static) {
+    println!("Global hotkeys are not supported on this platform");
+    reload(config);
+}
+
+// Nothing to register, but broken bindings are still worth pointing out
+#[cfg(not(target_os = "windows"))]
+pub fn reload(config: &HotkeyConfig) {
+    *FAILURES.lock().unwrap() = config.problems();
+}
+
+#[cfg(target_os = "windows")]
+pub fn start(config: &HotkeyConfig, handler: impl Fn(HotkeyAction) + Send + Sync + 'static) {
+    if HANDLER.set(Box::new(handler)).is_err() {
+        return;
+    }
+    *PENDING.lock().unwrap() = Some(config.clone());
+    std::thread::spawn(run_hotkey_thread);
+}
+
+// Register a changed set of bindings
+#[cfg(target_os = "windows")]
+pub fn reload(config: &HotkeyConfig) {
+    *PENDING.lock().unwrap() = Some(config.clone());
+    let thread_id = THREAD_ID.load(Ordering::Relaxed);
+    // Not running yet: the thread picks PENDING up when it starts
+    if thread_id != 0 && unsafe { PostThreadMessageW(thread_id, WM_RELOAD_HOTKEYS, 0, 0) } == 0 {
+        eprintln!("Failed to ask the hotkey thread to reload");
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn run_hotkey_thread() {
+    let mut registered: Vec<HotkeyAction> = Vec::new();
+    unsafe {
+        let mut msg: MSG = std::mem::zeroed();
+        // Make sure the thread has a message queue before anyone posts to it
+        PeekMessageW(&mut msg, std::ptr::null_mut(), 0, 0, PM_NOREMOVE);
+        THREAD_ID.store(GetCurrentThreadId(), Ordering::Relaxed);
+        register_pending(&mut registered);
+
+        while GetMessageW(&mut msg, std::ptr::null_mut(), 0, 0) > 0 {
+            match msg.message {
+                WM_HOTKEY => {
+                    if let (Some(action), Some(handler)) = (registered.get(msg.wParam), HANDLER.get()) {
+                        println!("Hotkey pressed: {:?}", action);
+                        handler(*action);
+                    }
+                }
+                WM_RELOAD_HOTKEYS => register_pending(&mut registered),
+                _ => {}
+            }
+        }
+    }
+}
+
+// Hotkey ids are indexes into `registered`
+#[cfg(target_os = "windows")]
+fn register_pending(registered: &mut Vec<HotkeyAction>) {
+    let Some(config) = PENDING.lock().unwrap().take() else { return };
+
+    for id in 0..registered.len() {
+        unsafe { UnregisterHotKey(std::ptr::null_mut(), id as i32) };
+    }
+    registered.clear();
+
+    let mut failures = config.problems();
+    for binding in &config.bindings {
+        if failures.iter().any(|(action, _)| *action == binding.action) {
+            continue;
+        }
+        let Ok(combo) = binding.keys.parse::<KeyCombo>() else { continue };
+        let Some(key) = virtual_key(&combo.key) else {
+            failures.push((binding.action, format!("{} can't be used as a hotkey", combo.key)));
+            continue;
+        };
+        let mut modifiers = MOD_NOREPEAT;
+        for (held, flag) in [(combo.ctrl, MOD_CONTROL), (combo.alt, MOD_ALT), (combo.shift, MOD_SHIFT), (combo.win, MOD_WIN)] {
+            if held {
+                modifiers |= flag;
+            }
+        }
+
+        let id = registered.len();
+        if unsafe { RegisterHotKey(std::ptr::null_mut(), id as i32, modifiers as u32, key) } == 0 {
+            eprintln!("Failed to register hotkey {} for {:?}", combo, binding.action);
+            failures.push((binding.action, format!("{} is already used by another program", combo)));
+        } else {
+            println!("Registered hotkey {} for {:?}", combo, binding.action);
+        }
+        // Keep the ids in step even when registration failed
+        registered.push(binding.action);
+    }
+    *FAILURES.lock().unwrap() = failures;
+}
+
+#[cfg(target_os = "windows")]
+fn virtual_key(key: &str) -> Option<u32> {
+    let code = match key {
+        "Up" => 0x26,
+        "Down" => 0x28,
+        "Left" => 0x25,
+        "Right" => 0x27,
+        "PageUp" => 0x21,
+        "PageDown" => 0x22,
+        "Home" => 0x24,
+        "End" => 0x23,
+        "Insert" => 0x2D,
+        "Delete" => 0x2E,
+        "Space" => 0x20,
+        "Minus" => 0xBD,
+        "Plus" => 0xBB,
+        _ => {
+            if let Some(number) = key.strip_prefix('F').and_then(|number| number.parse::<u32>().ok()) {
+                // VK_F1 is 0x70, the rest follow in order
+                return Some(0x6F + number);
+            }
+            // Letters and digits share their ASCII codes
+            let c = key.chars().next()?;
+            return c.is_ascii_alphanumeric().then_some(c as u32);
+        }
+    };
+    Some(code)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use eframe::egui::Key;
+
+    fn parse(text: &str) -> Result<KeyCombo, HotkeyError> {
+        text.parse()
+    }
+
+    #[test]
+    fn parses_modifiers_and_key() {
+        let combo = parse("Ctrl+Alt+R").unwrap();
+        assert_eq!(combo, KeyCombo { ctrl: true, alt: true, shift: false, win: false, key: "R".to_owned() });
+        let combo = parse(" win + shift + F12 ").unwrap();
+        assert_eq!(combo, KeyCombo { ctrl: false, alt: false, shift: true, win: true, key: "F12".to_owned() });
+        assert_eq!(parse("Control+Super+Space").unwrap().to_string(), "Ctrl+Win+Space");
+    }
+
+    #[test]
+    fn ignores_case_and_writes_the_canonical_spelling() {
+        assert_eq!(parse("ctrl+ALT+pageup").unwrap().to_string(), "Ctrl+Alt+PageUp");
+        assert_eq!(parse("CTRL+alt+r").unwrap(), parse("Ctrl+Alt+R").unwrap());
+        assert_eq!(parse("Alt+f5").unwrap().key, "F5");
+        // Modifiers come out in a fixed order whatever the input order
+        assert_eq!(parse("Shift+Alt+Ctrl+7").unwrap().to_string(), "Ctrl+Alt+Shift+7");
+    }
+
+    #[test]
+    fn rejects_repeated_modifiers() {
+        assert!(parse("Ctrl+Ctrl+R").is_err());
+        assert!(parse("Ctrl+Control+R").is_err());
+        assert!(parse("Alt+alt+R").is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_keys_and_modifiers() {
+        for text in ["Ctrl+Alt+Escape", "Ctrl+Alt+F0", "Ctrl+Alt+F25", "Ctrl+Alt+RR", "Ctrl+Alt+", "", "Hyper+R", "Ctrl+Alt+#"] {
+            assert!(parse(text).is_err(), "{} should not parse", text);
+        }
+    }
+
+    #[test]
+    fn needs_a_modifier_other_than_shift() {
+        assert!(parse("R").is_err());
+        assert!(parse("Shift+R").is_err());
+        assert!(parse("Win+R").is_ok());
+    }
+
+    #[test]
+    fn egui_keys_use_config_spelling() {
+        assert_eq!(egui_key_name(Key::A).as_deref(), Some("A"));
+        assert_eq!(egui_key_name(Key::Num1).as_deref(), Some("1"));
+        assert_eq!(egui_key_name(Key::F20).as_deref(), Some("F20"));
+        assert_eq!(egui_key_name(Key::ArrowUp).as_deref(), Some("Up"));
+        assert_eq!(egui_key_name(Key::PlusEquals).as_deref(), Some("Plus"));
+        assert_eq!(egui_key_name(Key::Escape), None);
+        assert_eq!(egui_key_name(Key::Tab), None);
+        // Whatever the settings window records parses back
+        for key in [Key::Z, Key::Num0, Key::F1, Key::PageDown, Key::Minus] {
+            let name = egui_key_name(key).unwrap();
+            assert_eq!(parse(&format!("Ctrl+{}", name)).unwrap().key, name);
+        }
+    }
+
+    #[test]
+    fn problems_name_the_bindings_that_cant_be_used() {
+        let config = HotkeyConfig {
+            bindings: vec![
+                Binding { action: HotkeyAction::Toggle, keys: "Ctrl+Alt+R".to_owned() },
+                Binding { action: HotkeyAction::CycleProfile, keys: "ctrl+alt+r".to_owned() },
+                Binding { action: HotkeyAction::Pause, keys: "Ctrl+Alt+Nope".to_owned() },
+                Binding { action: HotkeyAction::IntensityUp, keys: "Ctrl+Alt+PageUp".to_owned() },
+            ],
+            ..HotkeyConfig::default()
+        };
+        let actions: Vec<HotkeyAction> = config.problems().into_iter().map(|(action, _)| action).collect();
+        assert_eq!(actions, [HotkeyAction::CycleProfile, HotkeyAction::Pause]);
+        assert!(HotkeyConfig::default().problems().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+// Single-instance guard for the controller.
+//
+// The first controller takes an exclusive lock on `instance.lock` in the config directory and
+// holds it for as long as it runs (the OS drops it if we crash). It also listens on a loopback
+// port that it advertises in `instance.json`. A second launch fails to take the lock, reads the
+// port and forwards its request (e.g. "show settings") to the running instance, then exits.
+// The command line (`redshift set ...`, `redshift status`, ...) uses the same channel.
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+use fs2::FileExt;
+use serde::{Serialize, Deserialize};
+use crate::controller::ControllerStatus;
+use crate::ipc::{new_token, read_message, write_message};
+use crate::overlay::{self, TintMode};
+use crate::profile::Profile;
+
+const FORWARD_TIMEOUT: Duration = Duration::from_secs(2);
+// The running instance may have taken the lock but not written instance.json yet
+const FORWARD_ATTEMPTS: u32 = 10;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "request", rename_all = "snake_case")]
+pub enum InstanceRequest {
+    ShowSettings,
+    Set {
+        opacity: Option<u8>,
+        color_temperature: Option<u32>,
+        tint_mode: Option<TintMode>,
+        tint_color: Option<[u8; 3]>,
+        brightness: Option<u8>,
+    },
+    Toggle,
+    Status,
+    Preset { name: String },
+    ListProfiles,
+    UseProfile { name: String },
+    CreateProfile { name: String },
+    SaveProfile { name: String },
+    RenameProfile { from: String, to: String },
+    DeleteProfile { name: String },
+    SetDefaultProfile { name: Option<String> },
+    // None pauses until the next sunrise
+    Pause { minutes: Option<u32> },
+    Resume,
+    Quit,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "reply", rename_all = "snake_case")]
+pub enum InstanceReply {
+    Ok,
+    Status { status: ControllerStatus },
+    Profiles { profiles: Vec<Profile>, active: Option<String>, default: Option<String> },
+    Error { message: String },
+}
+
+#[derive(Serialize, Deserialize)]
+struct InstanceInfo {
+    port: u16,
+    token: String,
+    pid: u32,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Envelope {
+    token: String,
+    #[serde(flatten)]
+    request: InstanceRequest,
+}
+
+// Held by the running controller; dropping it releases the lock
+pub struct InstanceGuard {
+    _lock: File,
+    listener: TcpListener,
+    token: String,
+}
+
+pub enum Acquired {
+    // We are the only controller
+    Primary(InstanceGuard),
+    // Another controller is running and has handled the request
+    Forwarded(InstanceReply),
+}
+
+fn config_dir() -> PathBuf {
+    let config_path = overlay::config_path();
+    config_path.parent().map(|dir| dir.to_path_buf()).unwrap_or_default()
+}
+
+// instance.json holds the token, so other users on the machine mustn't be able to read it.
+// %LOCALAPPDATA% is only open to its user (plus SYSTEM and administrators) already.
+#[cfg(target_os = "windows")]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    fs::write(path, contents)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn write_private(path: &Path, contents: &str) -> io::Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+
+    let mut file = OpenOptions::new().write(true).create(true).truncate(true).mode(0o600).open(path)?;
+    // The mode only applies to new files; one left by an older version may be world-readable
+    file.set_permissions(fs::Permissions::from_mode(0o600))?;
+    file.write_all(contents.as_bytes())
+}
+
+// Become the running instance, or hand `request` to the one that already is
+pub fn acquire(request: InstanceRequest) -> io::Result<Acquired> {
+    let dir = config_dir();
+    fs::create_dir_all(&dir)?;
+
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(dir.join("instance.lock"))?;
+
+    if lock.try_lock_exclusive().is_err() {
+        return forward(request).map(Acquired::Forwarded);
+    }
+
+    let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+    let info = InstanceInfo {
+        port: listener.local_addr()?.port(),
+        token: new_token()?,
+        pid: std::process::id(),
+    };
+    write_private(&dir.join("instance.json"), &serde_json::to_string_pretty(&info)?)?;
+
+    Ok(Acquired::Primary(InstanceGuard {
+        _lock: lock,
+        listener,
+        token: info.token,
+    }))
+}
+
+// Whether some controller currently holds the instance lock
+pub fn is_running() -> bool {
+    let lock = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(config_dir().join("instance.lock"));
+    match lock {
+        Ok(lock) => match lock.try_lock_exclusive() {
+            Ok(()) => {
+                let _ = lock.unlock();
+                false
+            }
+            Err(_) => true,
+        },
+        Err(_) => false,
+    }
+}
+
+// Send a request to the running instance and wait for its reply
+pub fn forward(request: InstanceRequest) -> io::Result<InstanceReply> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no running instance");
+
+    for _ in 0..FORWARD_ATTEMPTS {
+        match try_forward(&request) {
+            Ok(reply) => return Ok(reply),
+            Err(e) => last_error = e,
+        }
+        thread::sleep(Duration::from_millis(200));
+    }
+    Err(last_error)
+}
+
+fn try_forward(request: &InstanceRequest) -> io::Result<InstanceReply> {
+    let info: InstanceInfo = serde_json::from_str(&fs::read_to_string(config_dir().join("instance.json"))?)?;
+
+    let mut stream = TcpStream::connect_timeout(&(Ipv4Addr::LOCALHOST, info.port).into(), FORWARD_TIMEOUT)?;
+    stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+    write_message(&mut stream, &Envelope { token: info.token, request: request.clone() })?;
+
+    read_message(&mut BufReader::new(stream))?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "instance closed the connection"))
+}
+
+impl InstanceGuard {
+    // Answer requests from later launches on a background thread
+    pub fn serve<F>(self, handler: F)
+    where
+        F: Fn(InstanceRequest) -> InstanceReply + Send + 'static,
+    {
+        thread::spawn(move || {
+            // Keep the lock alive for as long as we're serving
+            let guard = self;
+
+            for incoming in guard.listener.incoming() {
+                let mut stream = match incoming {
+                    Ok(stream) => stream,
+                    Err(e) => {
+                        eprintln!("Failed to accept instance connection: {}", e);
+                        continue;
+                    }
+                };
+                if let Err(e) = guard.handle(&mut stream, &handler) {
+                    eprintln!("Failed to handle instance request: {}", e);
+                }
+            }
+        });
+    }
+
+    fn handle<F>(&self, stream: &mut TcpStream, handler: &F) -> io::Result<()>
+    where
+        F: Fn(InstanceRequest) -> InstanceReply,
+    {
+        stream.set_read_timeout(Some(FORWARD_TIMEOUT))?;
+        let envelope: Envelope = match read_message(&mut BufReader::new(stream.try_clone()?))? {
+            Some(envelope) => envelope,
+            None => return Ok(()),
+        };
+
+        let reply = if envelope.token == self.token {
+            println!("Instance request from another launch: {:?}", envelope.request);
+            handler(envelope.request)
+        } else {
+            InstanceReply::Error { message: "bad instance token".to_owned() }
+        };
+        write_message(stream, &reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[cfg(not(target_os = "windows"))]
+    #[test]
+    fn instance_file_is_only_readable_by_its_owner() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = std::env::temp_dir().join(format!("redshift-instance-test-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("instance.json");
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        write_private(&path, "{}").unwrap();
+        assert_eq!(mode(&path), 0o600);
+
+        // Left behind world-readable by an older version
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(&path, r#"{ "token": "abc" }"#).unwrap();
+        assert_eq!(mode(&path), 0o600);
+        assert_eq!(fs::read_to_string(&path).unwrap(), r#"{ "token": "abc" }"#);
+
+        let _ = fs::remove_dir_all(&dir);
+    }
+}
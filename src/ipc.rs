@@ -0,0 +1,257 @@
+// Controller <-> overlay IPC over a loopback socket.
+//
+// The controller listens on an ephemeral 127.0.0.1 port and passes the port plus a one-off
+// token to the overlay on its command line. The overlay connects back, says hello with the
+// token, and from then on the controller sends one JSON request per line and the overlay
+// answers each one with an ack carrying its current status. The overlay also pushes its status
+// unprompted when its monitors change.
+
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener, TcpStream};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use serde::{Serialize, Deserialize};
+use serde::de::DeserializeOwned;
+use crate::backend::Output;
+use crate::monitor::MonitorSettings;
+use crate::overlay::TintMode;
+use crate::rules::RuleEffect;
+
+// How long the overlay gets to connect back after being spawned
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+const HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Controller -> overlay
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "command", rename_all = "snake_case")]
+pub enum Command {
+    SetOpacity { opacity: u8 },
+    SetColor { tint_mode: TintMode, color_temperature: u32, tint_color: [u8; 3] },
+    SetBrightness { brightness: u8 },
+    Pause,
+    Resume,
+    Shutdown,
+    SetMonitors { monitors: Vec<MonitorSettings> },
+    // None when no rule applies to the app in the foreground
+    SetRuleEffect { effect: Option<RuleEffect> },
+    Status,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Request {
+    pub id: u64,
+    #[serde(flatten)]
+    pub command: Command,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct OverlayStatus {
+    // Alpha currently targeted, after the schedule is applied
+    pub opacity: u8,
+    pub rgb: [u8; 3],
+    pub paused: bool,
+    pub shutting_down: bool,
+    // Monitors the overlay is drawing on
+    #[serde(default)]
+    pub outputs: Vec<Output>,
+}
+
+// Overlay -> controller
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "message", rename_all = "snake_case")]
+pub enum OverlayMessage {
+    Hello { token: String, pid: u32 },
+    Ack { id: u64, status: OverlayStatus },
+    Status { status: OverlayStatus },
+}
+
+pub fn write_message<T: Serialize>(stream: &mut impl Write, message: &T) -> io::Result<()> {
+    let mut line = serde_json::to_string(message)?;
+    line.push('\n');
+    stream.write_all(line.as_bytes())?;
+    stream.flush()
+}
+
+// Ok(None) means the other side closed the connection
+pub fn read_message<T: DeserializeOwned>(reader: &mut impl BufRead) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if reader.read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+// Secret a client has to present first. Any local user can connect to a loopback port, so it
+// comes from the OS random source rather than anything guessable like the time or a PID.
+pub fn new_token() -> io::Result<String> {
+    let mut bytes = [0u8; 16];
+    getrandom::fill(&mut bytes).map_err(|e| io::Error::other(format!("no random source: {}", e)))?;
+    Ok(bytes.iter().map(|byte| format!("{:02x}", byte)).collect())
+}
+
+// Overlay side: connect back to the controller and identify ourselves
+pub fn connect(port: u16, token: &str) -> io::Result<TcpStream> {
+    let mut stream = TcpStream::connect((Ipv4Addr::LOCALHOST, port))?;
+    write_message(&mut stream, &OverlayMessage::Hello {
+        token: token.to_owned(),
+        pid: std::process::id(),
+    })?;
+    Ok(stream)
+}
+
+// Controller side of the channel to one overlay process
+pub struct OverlayLink {
+    port: u16,
+    token: String,
+    stream: Arc<Mutex<Option<TcpStream>>>,
+    status: Arc<Mutex<Option<OverlayStatus>>>,
+    closed: Arc<AtomicBool>,
+    next_id: AtomicU64,
+}
+
+impl OverlayLink {
+    pub fn listen() -> io::Result<Self> {
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?;
+        listener.set_nonblocking(true)?;
+
+        let link = Self {
+            port: listener.local_addr()?.port(),
+            token: new_token()?,
+            stream: Arc::new(Mutex::new(None)),
+            status: Arc::new(Mutex::new(None)),
+            closed: Arc::new(AtomicBool::new(false)),
+            next_id: AtomicU64::new(1),
+        };
+
+        let token = link.token.clone();
+        let stream = link.stream.clone();
+        let status = link.status.clone();
+        let closed = link.closed.clone();
+        thread::spawn(move || accept_overlay(listener, token, stream, status, closed));
+
+        Ok(link)
+    }
+
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    pub fn token(&self) -> &str {
+        &self.token
+    }
+
+    pub fn is_connected(&self) -> bool {
+        self.stream.lock().unwrap().is_some()
+    }
+
+    // Last status the overlay acked with or pushed
+    pub fn status(&self) -> Option<OverlayStatus> {
+        self.status.lock().unwrap().clone()
+    }
+
+    // Fire off a command; the ack arrives asynchronously and updates `status()`
+    pub fn send(&self, command: Command) -> io::Result<u64> {
+        let mut stream = self.stream.lock().unwrap();
+        let connected = stream.as_mut().ok_or_else(|| {
+            io::Error::new(io::ErrorKind::NotConnected, "overlay has not connected yet")
+        })?;
+
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        if let Err(e) = write_message(connected, &Request { id, command }) {
+            *stream = None;
+            return Err(e);
+        }
+        Ok(id)
+    }
+}
+
+impl Drop for OverlayLink {
+    fn drop(&mut self) {
+        self.closed.store(true, Ordering::Relaxed);
+        if let Some(stream) = self.stream.lock().unwrap().take() {
+            let _ = stream.shutdown(std::net::Shutdown::Both);
+        }
+    }
+}
+
+fn accept_overlay(
+    listener: TcpListener,
+    token: String,
+    stream_slot: Arc<Mutex<Option<TcpStream>>>,
+    status: Arc<Mutex<Option<OverlayStatus>>>,
+    closed: Arc<AtomicBool>,
+) {
+    let deadline = Instant::now() + CONNECT_TIMEOUT;
+
+    while !closed.load(Ordering::Relaxed) && Instant::now() < deadline {
+        let stream = match listener.accept() {
+            Ok((stream, _)) => stream,
+            Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+                thread::sleep(Duration::from_millis(50));
+                continue;
+            }
+            Err(e) => {
+                eprintln!("Failed to accept IPC connection: {}", e);
+                continue;
+            }
+        };
+
+        match handshake(&stream, &token) {
+            Ok(reader) => {
+                if closed.load(Ordering::Relaxed) {
+                    return;
+                }
+                *stream_slot.lock().unwrap() = Some(stream);
+                read_replies(reader, stream_slot, status);
+                return;
+            }
+            Err(e) => eprintln!("Rejected IPC connection: {}", e),
+        }
+    }
+
+    if !closed.load(Ordering::Relaxed) {
+        eprintln!("Overlay never connected over IPC");
+    }
+}
+
+fn handshake(stream: &TcpStream, token: &str) -> io::Result<BufReader<TcpStream>> {
+    stream.set_nonblocking(false)?;
+    stream.set_read_timeout(Some(HANDSHAKE_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    match read_message::<OverlayMessage>(&mut reader)? {
+        Some(OverlayMessage::Hello { token: received, pid }) if received == token => {
+            println!("Overlay process {} connected over IPC", pid);
+            stream.set_read_timeout(None)?;
+            Ok(reader)
+        }
+        _ => Err(io::Error::new(io::ErrorKind::PermissionDenied, "bad handshake")),
+    }
+}
+
+fn read_replies(
+    mut reader: BufReader<TcpStream>,
+    stream_slot: Arc<Mutex<Option<TcpStream>>>,
+    status: Arc<Mutex<Option<OverlayStatus>>>,
+) {
+    loop {
+        match read_message::<OverlayMessage>(&mut reader) {
+            Ok(Some(OverlayMessage::Ack { status: reported, .. }))
+            | Ok(Some(OverlayMessage::Status { status: reported })) => {
+                *status.lock().unwrap() = Some(reported);
+            }
+            Ok(Some(OverlayMessage::Hello { .. })) => {}
+            Ok(None) => {
+                println!("Overlay closed the IPC connection");
+                break;
+            }
+            Err(e) => {
+                eprintln!("IPC connection to overlay failed: {}", e);
+                break;
+            }
+        }
+    }
+    *stream_slot.lock().unwrap() = None;
+}
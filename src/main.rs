@@ -1,44 +1,95 @@
 #![windows_subsystem = "windows"]
 
+mod backend;
+mod cli;
+mod color;
+mod config;
+mod controller;
+mod fade;
+mod fullscreen;
+mod hotkey;
+mod instance;
+mod ipc;
+mod monitor;
 mod overlay;
+mod overlay_process;
+mod profile;
+mod rules;
+mod schedule;
+mod supervisor;
+mod tray;
 
+use clap::Parser;
 use eframe::{egui, NativeOptions, IconData};
-use std::process::{Command, Child};
 use std::sync::Mutex;
 use once_cell::sync::OnceCell;
-use systray::Application;
 use std::thread;
-use std::sync::mpsc::{Sender, Receiver, channel};
 use std::env;
+use image::{self, ImageFormat};
+use std::fs;
+use std::time::Duration;
+
+#[cfg(target_os = "windows")]
+use systray::Application;
+#[cfg(target_os = "windows")]
+use std::sync::mpsc::{Sender, Receiver, channel};
+#[cfg(target_os = "windows")]
 use winapi::um::winuser::{GetSystemMetrics, SM_CXSCREEN, SM_CYSCREEN};
+#[cfg(target_os = "windows")]
 use winreg::enums::*;
+#[cfg(target_os = "windows")]
 use winreg::RegKey;
-use std::path::PathBuf;
-use image::{self, ImageFormat};
-use std::fs;
+#[cfg(target_os = "windows")]
 use std::io::Write;
 
 // Define a custom error type that implements necessary traits
+#[cfg(target_os = "windows")]
 #[derive(Debug)]
 struct MenuError(String);
 
+#[cfg(target_os = "windows")]
 impl std::fmt::Display for MenuError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", self.0)
     }
 }
 
+#[cfg(target_os = "windows")]
 impl std::error::Error for MenuError {}
 
 // Make MenuError Send + Sync
+#[cfg(target_os = "windows")]
 unsafe impl Send for MenuError {}
+#[cfg(target_os = "windows")]
 unsafe impl Sync for MenuError {}
 
+const WINDOW_WIDTH: f32 = 270.0;
+const WINDOW_HEIGHT: f32 = 248.0;
+// Extra height per line of the monitor list and the hotkey editor
+const EXTRA_ROW_HEIGHT: f32 = 24.0;
+
 static WINDOW_VISIBLE: OnceCell<Mutex<bool>> = OnceCell::new();
+// Lets other threads wake the UI up, e.g. when a second launch asks us to show ourselves
+static EGUI_CTX: OnceCell<egui::Context> = OnceCell::new();
 
 struct ControllerApp {
     config: overlay::OverlayConfig,
-    overlay_process: Option<Child>,
+    // Profile editor row: open or not, the profile it edits, the name field and the last error
+    profile_editor: bool,
+    profile_target: Option<String>,
+    profile_name: String,
+    profile_error: Option<String>,
+    // Per-monitor settings shown, and the window height last asked for
+    monitor_list: bool,
+    window_height: f32,
+    // Hotkey editor shown, the action waiting for a key press and the last error
+    hotkey_list: bool,
+    hotkey_capture: Option<hotkey::HotkeyAction>,
+    hotkey_error: Option<String>,
+    // Why the last pause request was refused
+    pause_error: Option<String>,
+    // App rule editor shown
+    rule_list: bool,
 }
 
 impl ControllerApp {
@@ -60,48 +111,412 @@ impl ControllerApp {
 
         // Set the fonts
         cc.egui_ctx.set_fonts(fonts);
+        let _ = EGUI_CTX.set(cc.egui_ctx.clone());
 
         // Continue with the existing initialization
-        let config = load_config().unwrap_or_default();
+        let config = controller::config();
         
-        let mut app = Self {
+        Self {
             config,
-            overlay_process: None,
-        };
-        
-        if app.config.enabled {
-            app.start_overlay();
+            profile_editor: false,
+            profile_target: None,
+            profile_name: String::new(),
+            profile_error: None,
+            monitor_list: false,
+            window_height: WINDOW_HEIGHT,
+            hotkey_list: false,
+            hotkey_capture: None,
+            hotkey_error: None,
+            pause_error: None,
+            rule_list: false,
         }
-        
-        app
     }
+}
 
-    fn start_overlay(&mut self) {
-        if self.overlay_process.is_none() {
-            let current_exe = std::env::current_exe().expect("Failed to get current executable path");
-            
-            self.overlay_process = Some(
-                Command::new(&current_exe)
-                    .arg("--overlay")
-                    .arg("--opacity")
-                    .arg(self.config.opacity.to_string())
-                    .spawn()
-                    .expect("Failed to start overlay process")
+impl ControllerApp {
+    fn profile_editor_row(&mut self, ui: &mut egui::Ui) {
+        let mut result = None;
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.add(
+                egui::TextEdit::singleline(&mut self.profile_name)
+                    .desired_width(90.0)
+                    .hint_text("Profile name")
             );
+
+            // Overwrite the profile being edited, or create a new one under a new name
+            if ui.small_button("Save").on_hover_text("Save the current settings under this name").clicked() {
+                result = Some(match &self.profile_target {
+                    Some(target) if target.eq_ignore_ascii_case(self.profile_name.trim()) => {
+                        controller::save_profile(target).map(|_| Some(target.clone()))
+                    }
+                    _ => controller::create_profile(&self.profile_name).map(Some),
+                });
+            }
+
+            let Some(target) = self.profile_target.clone() else { return };
+            if ui.small_button("Rename").clicked() {
+                result = Some(controller::rename_profile(&target, &self.profile_name).map(Some));
+            }
+            if ui.small_button("Delete").clicked() {
+                result = Some(controller::delete_profile(&target).map(|_| None));
+            }
+            let is_default = self.config.default_profile.as_ref() == Some(&target);
+            if ui.selectable_label(is_default, "Default")
+                .on_hover_text("Switch to this profile when RedShift starts")
+                .clicked()
+            {
+                let default = if is_default { None } else { Some(target.as_str()) };
+                result = Some(controller::set_default_profile(default).map(|_| Some(target.clone())));
+            }
+        });
+
+        match result {
+            Some(Ok(target)) => {
+                self.profile_name = target.clone().unwrap_or_default();
+                self.profile_target = target;
+                self.profile_error = None;
+            }
+            Some(Err(e)) => self.profile_error = Some(e.to_string()),
+            None => {}
+        }
+
+        if let Some(error) = &self.profile_error {
+            ui.vertical_centered(|ui| {
+                ui.label(egui::RichText::new(error).size(12.0).color(egui::Color32::from_rgb(220, 40, 40)));
+            });
         }
     }
 
-    fn stop_overlay(&mut self) {
-        if let Some(mut process) = self.overlay_process.take() {
-            let _ = process.kill();
+    // "Pause" with the quick lengths, or the countdown and "Resume" while paused
+    fn pause_menu(&mut self, ui: &mut egui::Ui) {
+        let paused_until = controller::paused_until();
+        let title = match paused_until {
+            Some(until) => format!(
+                "Paused {}",
+                controller::format_remaining(until.duration_since(std::time::SystemTime::now()).unwrap_or_default())
+            ),
+            None => "Pause".to_owned(),
+        };
+        let mut result = None;
+
+        ui.menu_button(egui::RichText::new(title).size(13.0), |ui| {
+            if let Some(until) = paused_until {
+                ui.label(egui::RichText::new(format!("Until {}", controller::format_clock(until))).size(12.0));
+                if ui.button("Resume").clicked() {
+                    controller::resume();
+                    ui.close_menu();
+                }
+                return;
+            }
+            for &minutes in controller::PAUSE_MINUTES {
+                if ui.button(pause_label(minutes)).clicked() {
+                    result = Some(controller::pause_for(Duration::from_secs(minutes as u64 * 60)));
+                    ui.close_menu();
+                }
+            }
+            if ui.button("Pause until sunrise").clicked() {
+                result = Some(controller::pause_until_sunrise());
+                ui.close_menu();
+            }
+        });
+
+        match result {
+            Some(Err(e)) => self.pause_error = Some(e.to_string()),
+            Some(Ok(_)) => self.pause_error = None,
+            None => {}
         }
     }
 
-    fn update_opacity(&mut self, new_opacity: u8) {
-        let clamped_opacity = new_opacity.clamp(90, 200);
-        println!("Controller updating opacity to: {}", clamped_opacity);
-        self.config.opacity = clamped_opacity;
-        save_config(&self.config);
+    // One line per action with its binding; click the binding, then press the new keys
+    fn hotkey_rows(&mut self, ui: &mut egui::Ui) {
+        let failures = hotkey::failures();
+        let mut result = None;
+
+        for &action in hotkey::ACTIONS {
+            ui.horizontal(|ui| {
+                ui.add_space(12.0);
+                ui.add_sized([100.0, 20.0], egui::Label::new(egui::RichText::new(action.label()).size(13.0)));
+
+                let capturing = self.hotkey_capture == Some(action);
+                let binding = self.config.hotkeys.binding(action);
+                let failure = failures.iter().find(|(failed, _)| *failed == action);
+                let mut text = egui::RichText::new(if capturing { "Press keys..." } else { binding.unwrap_or("None") }).size(12.0);
+                if failure.is_some() {
+                    text = text.color(egui::Color32::from_rgb(220, 40, 40));
+                }
+                let mut response = ui.selectable_label(capturing, text);
+                if let Some((_, reason)) = failure {
+                    response = response.on_hover_text(reason);
+                }
+                if response.clicked() {
+                    self.hotkey_capture = if capturing { None } else { Some(action) };
+                    self.hotkey_error = None;
+                }
+
+                if binding.is_some() {
+                    ui.add_space(4.0);
+                    if ui.small_button("x").on_hover_text("Remove this hotkey").clicked() {
+                        result = Some(controller::set_hotkey(action, None));
+                    }
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            ui.add_sized([100.0, 20.0], egui::Label::new(egui::RichText::new("Pause length").size(13.0)));
+            let mut minutes = self.config.hotkeys.pause_minutes;
+            if ui.add(egui::DragValue::new(&mut minutes).clamp_range(1..=24 * 60).suffix(" min")).changed() {
+                controller::update(|config| config.hotkeys.pause_minutes = minutes);
+            }
+        });
+
+        if let Some(Err(e)) = result {
+            self.hotkey_error = Some(e.to_string());
+        }
+        if let Some(error) = &self.hotkey_error {
+            ui.vertical_centered(|ui| {
+                ui.label(egui::RichText::new(error).size(12.0).color(egui::Color32::from_rgb(220, 40, 40)));
+            });
+        }
+    }
+
+    // Two lines per rule (what it matches, what it does), plus one to add a rule
+    fn rule_rows(&mut self, ui: &mut egui::Ui) {
+        let mut rules = self.config.rules.clone();
+        let mut changed = false;
+        let mut remove = None;
+        let mut raise = None;
+
+        for (index, rule) in rules.iter_mut().enumerate() {
+            ui.horizontal(|ui| {
+                ui.add_space(12.0);
+                changed |= ui.add(
+                    egui::TextEdit::singleline(&mut rule.pattern)
+                        .desired_width(120.0)
+                        .hint_text("app.exe")
+                ).on_hover_text("Case doesn't matter, * matches anything").changed();
+                ui.add_space(4.0);
+                let (label, other) = match rule.target {
+                    rules::RuleTarget::Executable => ("exe", rules::RuleTarget::WindowClass),
+                    rules::RuleTarget::WindowClass => ("class", rules::RuleTarget::Executable),
+                };
+                if ui.small_button(label).on_hover_text("Match the program file or the window class").clicked() {
+                    rule.target = other;
+                    changed = true;
+                }
+                if index > 0 {
+                    ui.add_space(4.0);
+                    if ui.small_button("^").on_hover_text("Check this rule earlier").clicked() {
+                        raise = Some(index);
+                    }
+                }
+                ui.add_space(4.0);
+                if ui.small_button("x").on_hover_text("Remove this rule").clicked() {
+                    remove = Some(index);
+                }
+            });
+
+            ui.horizontal(|ui| {
+                ui.add_space(24.0);
+                let opacity = match rule.action {
+                    rules::RuleAction::Opacity(opacity) => opacity,
+                    _ => self.config.opacity,
+                };
+                let profile = match &rule.action {
+                    rules::RuleAction::Profile(name) => Some(name.clone()),
+                    _ => self.config.profiles.first().map(|profile| profile.name.clone()),
+                };
+                let is_opacity = matches!(rule.action, rules::RuleAction::Opacity(_));
+                let is_profile = matches!(rule.action, rules::RuleAction::Profile(_));
+                if ui.selectable_label(rule.action == rules::RuleAction::Disable, egui::RichText::new("Off").size(12.0)).clicked() {
+                    rule.action = rules::RuleAction::Disable;
+                    changed = true;
+                }
+                ui.add_space(4.0);
+                if ui.selectable_label(is_opacity, egui::RichText::new("Opacity").size(12.0)).clicked() && !is_opacity {
+                    rule.action = rules::RuleAction::Opacity(opacity);
+                    changed = true;
+                }
+                if let Some(profile) = profile {
+                    ui.add_space(4.0);
+                    if ui.selectable_label(is_profile, egui::RichText::new("Profile").size(12.0)).clicked() && !is_profile {
+                        rule.action = rules::RuleAction::Profile(profile);
+                        changed = true;
+                    }
+                }
+
+                ui.add_space(8.0);
+                match &mut rule.action {
+                    rules::RuleAction::Disable => {}
+                    rules::RuleAction::Opacity(opacity) => {
+                        changed |= ui.add(egui::DragValue::new(opacity).clamp_range(90..=200)).changed();
+                    }
+                    rules::RuleAction::Profile(name) => {
+                        egui::ComboBox::from_id_source(("rule_profile", index))
+                            .width(70.0)
+                            .selected_text(egui::RichText::new(name.as_str()).size(12.0))
+                            .show_ui(ui, |ui| {
+                                for profile in &self.config.profiles {
+                                    changed |= ui.selectable_value(name, profile.name.clone(), &profile.name).changed();
+                                }
+                            });
+                    }
+                }
+            });
+        }
+
+        ui.horizontal(|ui| {
+            ui.add_space(12.0);
+            // Offer the app that last had the focus, since that's usually the one to add
+            let last_app = controller::foreground()
+                .map(|(app, _)| app.executable)
+                .filter(|executable| !executable.is_empty());
+            let label = match &last_app {
+                Some(executable) => format!("Add rule for {}", executable),
+                None => "Add rule".to_owned(),
+            };
+            if ui.small_button(label).clicked() {
+                rules.push(rules::AppRule {
+                    pattern: last_app.unwrap_or_default(),
+                    target: rules::RuleTarget::Executable,
+                    action: rules::RuleAction::Disable,
+                });
+                changed = true;
+            }
+            if let Some((_, Some(pattern))) = controller::foreground() {
+                ui.add_space(8.0);
+                ui.label(
+                    egui::RichText::new(format!("Active: {}", pattern))
+                        .size(12.0)
+                        .color(egui::Color32::from_rgb(120, 120, 120))
+                );
+            }
+        });
+
+        if let Some(index) = remove {
+            rules.remove(index);
+            changed = true;
+        }
+        if let Some(index) = raise {
+            rules.swap(index - 1, index);
+            changed = true;
+        }
+        if changed {
+            controller::set_rules(rules);
+        }
+    }
+
+    // The next key pressed while an action waits for one becomes its hotkey; Escape cancels
+    fn capture_hotkey(&mut self, ctx: &egui::Context) {
+        let Some(action) = self.hotkey_capture else { return };
+        let pressed = ctx.input(|input| input.events.iter().find_map(|event| match event {
+            egui::Event::Key { key, pressed: true, modifiers, .. } => Some((*key, *modifiers)),
+            _ => None,
+        }));
+        let Some((key, modifiers)) = pressed else { return };
+
+        self.hotkey_capture = None;
+        if key == egui::Key::Escape {
+            return;
+        }
+        self.hotkey_error = match hotkey::egui_key_name(key) {
+            Some(name) => {
+                let combo = hotkey::KeyCombo { ctrl: modifiers.ctrl, alt: modifiers.alt, shift: modifiers.shift, win: false, key: name };
+                controller::set_hotkey(action, Some(&combo.to_string())).err().map(|e| e.to_string())
+            }
+            None => Some(format!("{:?} can't be used as a hotkey", key)),
+        };
+    }
+
+    // One line per monitor (enabled, own settings or not), plus a slider line for those with their own
+    fn monitor_rows(&mut self, ui: &mut egui::Ui, monitors: Vec<(monitor::MonitorSettings, bool)>) {
+        if monitors.is_empty() {
+            ui.vertical_centered(|ui| {
+                ui.label(
+                    egui::RichText::new("Enable the overlay to list monitors")
+                        .size(12.0)
+                        .color(egui::Color32::from_rgb(120, 120, 120))
+                );
+            });
+            return;
+        }
+
+        for (mut settings, connected) in monitors {
+            let before = settings.clone();
+            ui.horizontal(|ui| {
+                ui.add_space(12.0);
+                let label = if connected { settings.label() } else { format!("{} (unplugged)", settings.label()) };
+                ui.checkbox(&mut settings.enabled, egui::RichText::new(label).size(13.0))
+                    .on_hover_text("Tint this monitor");
+                let custom = settings.has_overrides();
+                if ui.selectable_label(custom, egui::RichText::new("Custom").size(12.0))
+                    .on_hover_text("Give this monitor its own intensity and color")
+                    .clicked()
+                {
+                    if custom {
+                        settings.opacity = None;
+                        settings.tint_mode = None;
+                        settings.color_temperature = None;
+                        settings.tint_color = None;
+                    } else {
+                        settings.opacity = Some(self.config.opacity);
+                        settings.tint_mode = Some(self.config.tint_mode);
+                        settings.color_temperature = Some(self.config.color_temperature);
+                        settings.tint_color = Some(self.config.tint_color);
+                    }
+                }
+            });
+
+            if settings.enabled && settings.has_overrides() {
+                ui.horizontal(|ui| {
+                    ui.add_space(36.0);
+                    let mode = settings.tint_mode.unwrap_or(self.config.tint_mode);
+                    let (label, other) = match mode {
+                        overlay::TintMode::Intensity => ("Color", overlay::TintMode::Temperature),
+                        overlay::TintMode::Temperature => ("Warm", overlay::TintMode::Intensity),
+                    };
+                    if ui.small_button(label).on_hover_text("Switch between a color and color temperature").clicked() {
+                        settings.tint_mode = Some(other);
+                    }
+                    ui.spacing_mut().slider_width = 130.0;
+                    match mode {
+                        overlay::TintMode::Intensity => {
+                            let mut tint_color = settings.tint_color.unwrap_or(self.config.tint_color);
+                            if ui.color_edit_button_srgb(&mut tint_color).on_hover_text("Tint color").changed() {
+                                settings.tint_color = Some(tint_color);
+                            }
+                            let mut opacity = settings.opacity.unwrap_or(self.config.opacity) as f32;
+                            if ui.add(
+                                egui::Slider::new(&mut opacity, 90.0..=200.0)
+                                    .custom_formatter(|value, _| format!("{}%", ((value - 90.0) / 110.0 * 100.0).round() as i32))
+                            ).changed() {
+                                settings.opacity = Some(opacity as u8);
+                            }
+                        }
+                        overlay::TintMode::Temperature => {
+                            let mut temperature = settings.color_temperature.unwrap_or(self.config.color_temperature) as f32;
+                            if ui.add(
+                                egui::Slider::new(
+                                    &mut temperature,
+                                    color::MIN_TEMPERATURE as f32..=color::MAX_TEMPERATURE as f32
+                                )
+                                    .step_by(100.0)
+                                    .custom_formatter(|value, _| format!("{}K", value.round() as u32))
+                            ).changed() {
+                                settings.color_temperature = Some(temperature as u32);
+                            }
+                        }
+                    }
+                });
+            }
+
+            if settings != before {
+                controller::set_monitor(settings);
+            }
+        }
     }
 }
 
@@ -124,6 +539,9 @@ impl eframe::App for ControllerApp {
         style.visuals.selection.bg_fill = egui::Color32::from_rgb(220, 40, 40);
         ctx.set_style(style.clone());
 
+        // Settings may have been changed from the tray or the command line
+        self.config = controller::config();
+
         // Window visibility check
         if let Some(visible) = WINDOW_VISIBLE.get() {
             let is_visible = *visible.lock().unwrap();
@@ -135,6 +553,33 @@ impl eframe::App for ControllerApp {
             }
         }
 
+        self.capture_hotkey(ctx);
+
+        // Grow the window to fit the monitor list and the hotkey and rule editors
+        let monitors = if self.monitor_list { controller::monitors() } else { Vec::new() };
+        let monitor_lines = monitors
+            .iter()
+            .map(|(settings, _)| if settings.enabled && settings.has_overrides() { 2 } else { 1 })
+            .sum::<usize>()
+            .max(self.monitor_list as usize);
+        let hotkey_lines = if self.hotkey_list {
+            hotkey::ACTIONS.len() + 1 + self.hotkey_error.is_some() as usize
+        } else {
+            0
+        };
+        let rule_lines = if self.rule_list { self.config.rules.len() * 2 + 1 } else { 0 };
+        let pause_lines = self.pause_error.is_some() as usize;
+        let extra_lines = monitor_lines + hotkey_lines + rule_lines + pause_lines;
+        let window_height = WINDOW_HEIGHT + extra_lines as f32 * EXTRA_ROW_HEIGHT;
+        if window_height != self.window_height {
+            // Keep the bottom edge where it was, the window sits just above the taskbar
+            if let Some(position) = frame.info().window_info.position {
+                frame.set_window_pos(position - egui::vec2(0.0, window_height - self.window_height));
+            }
+            frame.set_window_size(egui::vec2(WINDOW_WIDTH, window_height));
+            self.window_height = window_height;
+        }
+
         egui::CentralPanel::default().show(ctx, |ui| {
             ui.spacing_mut().item_spacing = egui::vec2(0.0, 4.0);
             
@@ -147,20 +592,46 @@ impl eframe::App for ControllerApp {
                         .strong()
                         .text_style(egui::TextStyle::Heading)
                 );
-                
-                // Add the new text here
-                ui.label(
-                    egui::RichText::new("Intensity")
-                        .color(egui::Color32::WHITE)
-                        .size(13.0)  // Half of 24.0
-                        .text_style(egui::TextStyle::Heading)
-                );
+            });
+
+            // Slider mode switch: intensity of the picked color or color temperature
+            ui.horizontal(|ui| {
+                ui.add_space(50.0);
+                for (mode, label) in [
+                    (overlay::TintMode::Intensity, "Intensity"),
+                    (overlay::TintMode::Temperature, "Temperature"),
+                ] {
+                    let selected = self.config.tint_mode == mode;
+                    let text_color = if selected {
+                        egui::Color32::WHITE
+                    } else {
+                        egui::Color32::from_rgb(120, 120, 120)
+                    };
+                    if ui.selectable_label(
+                        selected,
+                        egui::RichText::new(label)
+                            .color(text_color)
+                            .size(13.0)  // Half of 24.0
+                            .text_style(egui::TextStyle::Heading)
+                    ).clicked() && !selected {
+                        controller::set_tint_mode(mode);
+                    }
+                    ui.add_space(8.0);
+                }
+                // Every change goes straight to the overlay, so the screen previews the color
+                if self.config.tint_mode == overlay::TintMode::Intensity {
+                    let mut tint_color = self.config.tint_color;
+                    if ui.color_edit_button_srgb(&mut tint_color).on_hover_text("Tint color").changed() {
+                        controller::set_tint_color(tint_color);
+                    }
+                }
             });
             
             ui.add_space(4.0);
             
             // Define all positioning variables for opacity control
             let mut opacity = self.config.opacity as f32;
+            let mut temperature = self.config.color_temperature as f32;
             
             // Slider dimensions
             let slider_width = 220.0;
@@ -178,25 +649,192 @@ impl eframe::App for ControllerApp {
             slider_style.visuals.widgets.inactive.rounding = 2.0.into();  // Slightly rounded corners
             ctx.set_style(slider_style);
 
-            // Draw slider with percentage
-            if ui.put(
-                egui::Rect::from_min_size(
-                    egui::pos2(slider_x, slider_y),
-                    egui::Vec2::new(slider_width, slider_height)
-                ),
-                egui::Slider::new(&mut opacity, 90.0..=200.0)
-                    .text("")
-                    .custom_formatter(|value, _| {
-                        // Simple percentage calculation
-                        let percentage = ((value - 90.0) / 110.0 * 100.0).round() as i32;
-                        format!("{}%", percentage)
-                    })
-            ).changed() {
-                self.update_opacity(opacity as u8);
+            let slider_rect = egui::Rect::from_min_size(
+                egui::pos2(slider_x, slider_y),
+                egui::Vec2::new(slider_width, slider_height)
+            );
+
+            match self.config.tint_mode {
+                overlay::TintMode::Intensity => {
+                    // Draw slider with percentage
+                    if ui.put(
+                        slider_rect,
+                        egui::Slider::new(&mut opacity, 90.0..=200.0)
+                            .text("")
+                            .custom_formatter(|value, _| {
+                                // Simple percentage calculation
+                                let percentage = ((value - 90.0) / 110.0 * 100.0).round() as i32;
+                                format!("{}%", percentage)
+                            })
+                    ).changed() {
+                        controller::set_opacity(opacity as u8);
+                    }
+                }
+                overlay::TintMode::Temperature => {
+                    // Draw slider in Kelvin, lower values are warmer
+                    if ui.put(
+                        slider_rect,
+                        egui::Slider::new(
+                            &mut temperature,
+                            color::MIN_TEMPERATURE as f32..=color::MAX_TEMPERATURE as f32
+                        )
+                            .text("")
+                            .step_by(100.0)
+                            .custom_formatter(|value, _| format!("{}K", value.round() as u32))
+                    ).changed() {
+                        controller::set_temperature(temperature as u32);
+                    }
+                }
             }
 
+            // Darkening on top of the tint, for when the backlight won't go any lower
+            let mut brightness = self.config.brightness as f32;
+            ui.horizontal(|ui| {
+                ui.add_space(30.0);
+                ui.label(egui::RichText::new("Brightness").size(13.0));
+                ui.add_space(8.0);
+                ui.spacing_mut().slider_width = 110.0;
+                if ui.add(
+                    egui::Slider::new(
+                        &mut brightness,
+                        color::MIN_BRIGHTNESS as f32..=color::MAX_BRIGHTNESS as f32
+                    )
+                        .step_by(1.0)
+                        .custom_formatter(|value, _| format!("{}%", value.round() as u32))
+                ).changed() {
+                    controller::set_brightness(brightness as u8);
+                }
+            });
+
             ui.add_space(4.0);
 
+            // Profile picker, plus an editor row for saving, renaming and deleting profiles
+            let active_profile = controller::active_profile(&self.config);
+            ui.horizontal(|ui| {
+                ui.add_space(62.0);
+                egui::ComboBox::from_id_source("profile")
+                    .width(110.0)
+                    .selected_text(
+                        egui::RichText::new(active_profile.as_deref().unwrap_or("Custom")).size(13.0)
+                    )
+                    .show_ui(ui, |ui| {
+                        for profile in &self.config.profiles {
+                            let is_active = active_profile.as_ref() == Some(&profile.name);
+                            if ui.selectable_label(is_active, &profile.name).clicked() && !is_active {
+                                if let Err(e) = controller::use_profile(&profile.name) {
+                                    eprintln!("Failed to switch profile: {}", e);
+                                }
+                            }
+                        }
+                    });
+                ui.add_space(6.0);
+                if ui.small_button(if self.profile_editor { "Done" } else { "Edit" }).clicked() {
+                    self.profile_editor = !self.profile_editor;
+                    self.profile_target = active_profile.clone();
+                    self.profile_name = active_profile.clone().unwrap_or_default();
+                    self.profile_error = None;
+                }
+            });
+
+            if self.profile_editor {
+                self.profile_editor_row(ui);
+            }
+
+            ui.horizontal(|ui| {
+                let gap = if rules::supported() { 8.0 } else { 12.0 };
+                ui.add_space(match (hotkey::supported(), rules::supported()) {
+                    (true, true) => 16.0,
+                    (true, false) | (false, true) => 40.0,
+                    (false, false) => 75.0,
+                });
+                if ui.selectable_label(self.monitor_list, egui::RichText::new("Monitors").size(13.0)).clicked() {
+                    self.monitor_list = !self.monitor_list;
+                }
+                if hotkey::supported() {
+                    ui.add_space(gap);
+                    if ui.selectable_label(self.hotkey_list, egui::RichText::new("Hotkeys").size(13.0)).clicked() {
+                        self.hotkey_list = !self.hotkey_list;
+                        self.hotkey_capture = None;
+                        self.hotkey_error = None;
+                    }
+                }
+                if rules::supported() {
+                    ui.add_space(gap);
+                    if ui.selectable_label(self.rule_list, egui::RichText::new("Rules").size(13.0))
+                        .on_hover_text("Change the tint while certain apps have the focus")
+                        .clicked()
+                    {
+                        self.rule_list = !self.rule_list;
+                    }
+                }
+                ui.add_space(gap);
+                self.pause_menu(ui);
+            });
+            if let Some(error) = self.pause_error.clone() {
+                ui.vertical_centered(|ui| {
+                    let label = ui.label(
+                        egui::RichText::new(format!("Can't pause: {}", error))
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(220, 40, 40))
+                    ).on_hover_text("Click to dismiss");
+                    if label.interact(egui::Sense::click()).clicked() {
+                        self.pause_error = None;
+                    }
+                });
+            }
+            if controller::paused_until().is_some() {
+                // Tick the countdown
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+            if self.monitor_list {
+                self.monitor_rows(ui, monitors);
+                // Pick up monitors being plugged in or out
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+            if self.hotkey_list {
+                self.hotkey_rows(ui);
+            }
+            if self.rule_list {
+                self.rule_rows(ui);
+                // Follow the foreground app for the "Add rule" button
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+
+            // Surface overlay crashes instead of pretending everything is fine
+            let problem = match supervisor::health() {
+                supervisor::OverlayHealth::Restarting { attempt, reason } => {
+                    Some((format!("Overlay crashed, restarting ({})", attempt), reason))
+                }
+                supervisor::OverlayHealth::Failed { reason } => {
+                    Some(("Overlay failed, re-enable to retry".to_owned(), reason))
+                }
+                _ => None,
+            };
+            if let Some(config_problem) = controller::config_problem() {
+                ui.vertical_centered(|ui| {
+                    let label = ui.label(
+                        egui::RichText::new("Settings file was invalid (click to dismiss)")
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(220, 40, 40))
+                    ).on_hover_text(config_problem);
+                    if label.interact(egui::Sense::click()).clicked() {
+                        controller::dismiss_config_problem();
+                    }
+                });
+            }
+
+            if let Some((summary, reason)) = problem {
+                ui.vertical_centered(|ui| {
+                    ui.label(
+                        egui::RichText::new(summary)
+                            .size(12.0)
+                            .color(egui::Color32::from_rgb(220, 40, 40))
+                    ).on_hover_text(reason);
+                });
+                // Keep the status fresh while the window is open
+                ctx.request_repaint_after(std::time::Duration::from_secs(1));
+            }
+
             // Add space to push elements to bottom
             ui.add_space(ui.available_height() - 40.0);  // Reserve space for bottom row
 
@@ -207,16 +845,22 @@ impl eframe::App for ControllerApp {
                 // Left side: Checkboxes
                 ui.vertical(|ui| {
                     let mut enabled = self.config.enabled;
-                    ui.checkbox(&mut enabled, egui::RichText::new("Enable Overlay").size(14.0));
+                    let overlay_state = supervisor::with_process(|overlay| match overlay {
+                        Some(overlay) => match overlay.link() {
+                            Some(link) if link.is_connected() => match link.status() {
+                                Some(status) => format!("Overlay running, alpha {}", status.opacity),
+                                None => "Overlay running".to_owned(),
+                            },
+                            Some(_) => "Overlay starting...".to_owned(),
+                            None => "Overlay running (no IPC)".to_owned(),
+                        },
+                        None => "Overlay stopped".to_owned(),
+                    });
+                    ui.checkbox(&mut enabled, egui::RichText::new("Enable Overlay").size(14.0))
+                        .on_hover_text(overlay_state);
                     
                     if enabled != self.config.enabled {
-                        self.config.enabled = enabled;
-                        save_config(&self.config);
-                        if enabled {
-                            self.start_overlay();
-                        } else {
-                            self.stop_overlay();
-                        }
+                        controller::set_enabled(enabled);
                     }
 
                     let mut launch_on_startup = self.config.launch_on_startup;
@@ -224,11 +868,10 @@ impl eframe::App for ControllerApp {
                         egui::RichText::new("Launch on Startup").size(14.0));
                     
                     if launch_on_startup != self.config.launch_on_startup {
-                        self.config.launch_on_startup = launch_on_startup;
                         if let Err(e) = set_launch_on_startup(launch_on_startup) {
                             eprintln!("Failed to set startup: {}", e);
                         }
-                        save_config(&self.config);
+                        controller::update(|config| config.launch_on_startup = launch_on_startup);
                     }
                 });
 
@@ -259,76 +902,135 @@ impl eframe::App for ControllerApp {
     }
 }
 
-fn save_config(config: &overlay::OverlayConfig) {
-    let config_path = overlay::config_path();
-    println!("Saving config to: {}", config_path.display());
-    
-    let config_str = match serde_json::to_string_pretty(config) {
-        Ok(str) => str,
-        Err(e) => {
-            eprintln!("Failed to serialize config: {}", e);
-            return;
-        }
-    };
-
-    if let Some(parent) = config_path.parent() {
-        if let Err(e) = std::fs::create_dir_all(parent) {
-            eprintln!("Failed to create config directory: {}", e);
-            return;
-        }
+fn show_settings() {
+    if let Some(visible) = WINDOW_VISIBLE.get() {
+        *visible.lock().unwrap() = true;
     }
+    refresh_ui();
+}
 
-    match std::fs::write(&config_path, config_str) {
-        Ok(_) => println!("Successfully saved config"),
-        Err(e) => eprintln!("Failed to save config: {}", e),
+fn refresh_ui() {
+    if let Some(ctx) = EGUI_CTX.get() {
+        ctx.request_repaint();
     }
 }
 
-fn load_config() -> Option<overlay::OverlayConfig> {
-    let config_path = overlay::config_path();
-    println!("Loading config from: {}", config_path.display());
-    
-    let config_str = match std::fs::read_to_string(&config_path) {
-        Ok(str) => str,
-        Err(e) => {
-            eprintln!("Failed to read config file: {}", e);
-            return None;
-        }
-    };
-
-    match serde_json::from_str(&config_str) {
-        Ok(config) => Some(config),
-        Err(e) => {
-            eprintln!("Failed to parse config: {}", e);
-            None
-        }
+fn profiles_reply() -> instance::InstanceReply {
+    let config = controller::config();
+    instance::InstanceReply::Profiles {
+        active: controller::active_profile(&config),
+        default: config.default_profile,
+        profiles: config.profiles,
     }
 }
 
-enum TrayAction {
-    ShowSettings,
-    Exit,
+fn profile_reply(result: Result<(), profile::ProfileError>) -> instance::InstanceReply {
+    match result {
+        Ok(()) => profiles_reply(),
+        Err(e) => instance::InstanceReply::Error { message: e.to_string() },
+    }
 }
 
-fn kill_processes_by_name(name: &str) {
-    if cfg!(target_os = "windows") {
-        println!("Attempting to kill process: {}", name);
-        let output = Command::new("taskkill")
-            .args(["/F", "/IM", name])
-            .output();
-        
-        match output {
-            Ok(output) => {
-                println!("Taskkill output: {}", String::from_utf8_lossy(&output.stdout));
-                if !output.stderr.is_empty() {
-                    eprintln!("Taskkill error: {}", String::from_utf8_lossy(&output.stderr));
+// Requests from later launches: a plain launch or one of the CLI commands
+fn handle_instance_request(request: instance::InstanceRequest) -> instance::InstanceReply {
+    use instance::{InstanceReply, InstanceRequest};
+
+    let reply = match request {
+        InstanceRequest::ShowSettings => {
+            show_settings();
+            return InstanceReply::Ok;
+        }
+        InstanceRequest::Set { opacity, color_temperature, tint_mode, tint_color, brightness } => {
+            if let Some(mode) = tint_mode {
+                controller::set_tint_mode(mode);
+            }
+            if let Some(temperature) = color_temperature {
+                controller::set_temperature(temperature);
+            }
+            if let Some(tint_color) = tint_color {
+                controller::set_tint_color(tint_color);
+            }
+            if let Some(opacity) = opacity {
+                controller::set_opacity(opacity);
+            }
+            if let Some(brightness) = brightness {
+                controller::set_brightness(brightness);
+            }
+            InstanceReply::Status { status: controller::status() }
+        }
+        InstanceRequest::Toggle => {
+            controller::toggle();
+            InstanceReply::Status { status: controller::status() }
+        }
+        InstanceRequest::Status => InstanceReply::Status { status: controller::status() },
+        // Built-in presets first, then the user's profiles
+        InstanceRequest::Preset { name } => match controller::find_preset(&name) {
+            Some(preset) => {
+                controller::apply_preset(preset);
+                InstanceReply::Status { status: controller::status() }
+            }
+            None => match controller::use_profile(&name) {
+                Ok(()) => InstanceReply::Status { status: controller::status() },
+                Err(_) => {
+                    let config = controller::config();
+                    let presets: Vec<&str> = controller::PRESETS.iter().map(|preset| preset.name).collect();
+                    let profiles: Vec<&str> = config.profiles.iter().map(|profile| profile.name.as_str()).collect();
+                    InstanceReply::Error {
+                        message: format!(
+                            "unknown preset or profile '{}' (presets: {}; profiles: {})",
+                            name, presets.join(", "), profiles.join(", ")
+                        ),
+                    }
                 }
+            },
+        },
+        InstanceRequest::ListProfiles => profiles_reply(),
+        InstanceRequest::UseProfile { name } => match controller::use_profile(&name) {
+            Ok(()) => InstanceReply::Status { status: controller::status() },
+            Err(e) => InstanceReply::Error { message: e.to_string() },
+        },
+        InstanceRequest::CreateProfile { name } => profile_reply(controller::create_profile(&name).map(|_| ())),
+        InstanceRequest::SaveProfile { name } => profile_reply(controller::save_profile(&name)),
+        InstanceRequest::RenameProfile { from, to } => {
+            profile_reply(controller::rename_profile(&from, &to).map(|_| ()))
+        }
+        InstanceRequest::DeleteProfile { name } => profile_reply(controller::delete_profile(&name)),
+        InstanceRequest::SetDefaultProfile { name } => {
+            profile_reply(controller::set_default_profile(name.as_deref()))
+        }
+        InstanceRequest::Pause { minutes } => {
+            let paused = match minutes {
+                Some(minutes) => controller::pause_for(Duration::from_secs(minutes as u64 * 60)),
+                None => controller::pause_until_sunrise(),
+            };
+            match paused {
+                Ok(_) => InstanceReply::Status { status: controller::status() },
+                Err(e) => InstanceReply::Error { message: e.to_string() },
             }
-            Err(e) => eprintln!("Failed to execute taskkill: {}", e),
         }
-    }
+        InstanceRequest::Resume => {
+            controller::resume();
+            InstanceReply::Status { status: controller::status() }
+        }
+        InstanceRequest::Quit => {
+            // Reply first, the overlay's fade-out keeps us around a little longer
+            thread::spawn(|| {
+                controller::quit();
+            });
+            InstanceReply::Ok
+        }
+    };
+    refresh_ui();
+    reply
 }
 
+#[cfg(target_os = "windows")]
+enum TrayAction {
+    ShowSettings,
+    Exit,
+}
+
+#[cfg(target_os = "windows")]
 fn set_launch_on_startup(enable: bool) -> Result<(), Box<dyn std::error::Error>> {
     let hkcu = RegKey::predef(HKEY_CURRENT_USER);
     let path = "Software\\Microsoft\\Windows\\CurrentVersion\\Run";
@@ -345,8 +1047,34 @@ fn set_launch_on_startup(enable: bool) -> Result<(), Box<dyn std::error::Error>>
     Ok(())
 }
 
+// XDG autostart entry, picked up by most desktop environments
+#[cfg(target_os = "linux")]
+fn set_launch_on_startup(enable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    let config_home = config::xdg_config_home().ok_or("Neither XDG_CONFIG_HOME nor HOME is set")?;
+    let entry_path = config_home.join("autostart").join("redshift.desktop");
+
+    if enable {
+        let exe_path = std::env::current_exe()?;
+        fs::create_dir_all(config_home.join("autostart"))?;
+        fs::write(
+            &entry_path,
+            format!(
+                "[Desktop Entry]\nType=Application\nName=RedShift\nExec=\"{}\"\nX-GNOME-Autostart-enabled=true\n",
+                exe_path.display()
+            ),
+        )?;
+    } else {
+        fs::remove_file(&entry_path).ok(); // Ignore if not exists
+    }
+    Ok(())
+}
+
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn set_launch_on_startup(_enable: bool) -> Result<(), Box<dyn std::error::Error>> {
+    Err("Launch on startup is not supported on this platform".into())
+}
+
 const WINDOW_ICON_BYTES: &[u8] = include_bytes!("../assets/RSICONICO.ico");
-const BUTTON_ICON_BYTES: &[u8] = include_bytes!("../assets/RSICONICO.ico");
 
 fn load_icon() -> IconData {
     let image = image::load_from_memory_with_format(WINDOW_ICON_BYTES, ImageFormat::Ico)
@@ -360,10 +1088,74 @@ fn load_icon() -> IconData {
     }
 }
 
-fn run_controller() {
+// `opacity` comes from the old `--opacity` flag
+fn run_controller(opacity: Option<u8>) {
+    // A second launch (startup entry plus a manual click, say) just brings up the running one,
+    // or passes the intensity on
+    let request = match opacity {
+        Some(opacity) => instance::InstanceRequest::Set {
+            opacity: Some(opacity.clamp(90, 200)),
+            color_temperature: None,
+            tint_mode: None,
+            tint_color: None,
+            brightness: None,
+        },
+        None => instance::InstanceRequest::ShowSettings,
+    };
+    match instance::acquire(request) {
+        Ok(instance::Acquired::Primary(guard)) => {
+            // Settings have to be loaded before other launches can change them
+            controller::init();
+            if let Some(opacity) = opacity {
+                controller::set_opacity(opacity);
+            }
+            guard.serve(handle_instance_request);
+        }
+        Ok(instance::Acquired::Forwarded(reply)) => {
+            println!("RedShift is already running: {:?}", reply);
+            return;
+        }
+        Err(e) => {
+            eprintln!("RedShift is already running but could not be reached: {}", e);
+            return;
+        }
+    }
+
     WINDOW_VISIBLE.set(Mutex::new(true)).unwrap();
-    
-    let (tx, _rx): (Sender<TrayAction>, Receiver<TrayAction>) = channel();
+    supervisor::spawn_watchdog();
+    controller::watch_app_rules();
+    hotkey::start(&controller::config().hotkeys, |action| {
+        controller::run_hotkey(action);
+        refresh_ui();
+    });
+
+    #[cfg(target_os = "windows")]
+    let _tray_actions = spawn_tray();
+
+    let options = NativeOptions {
+        initial_window_size: Some(egui::vec2(WINDOW_WIDTH, WINDOW_HEIGHT)),
+        initial_window_pos: initial_window_pos(WINDOW_WIDTH, WINDOW_HEIGHT),
+        resizable: false,
+        decorated: true,
+        transparent: true,
+        always_on_top: true,
+        // No maximum: the window grows while the monitor list is open
+        min_window_size: Some(egui::vec2(WINDOW_WIDTH, WINDOW_HEIGHT)),
+        icon_data: Some(load_icon()),
+        ..Default::default()
+    };
+
+    eframe::run_native(
+        "RedShift Controller",
+        options,
+        Box::new(|cc| Box::new(ControllerApp::new(cc)))
+    ).expect("Failed to run eframe");
+}
+
+// Tray icon with its menu, running on its own thread
+#[cfg(target_os = "windows")]
+fn spawn_tray() -> Receiver<TrayAction> {
+    let (tx, rx): (Sender<TrayAction>, Receiver<TrayAction>) = channel();
     
     thread::spawn(move || {
         let mut app = Application::new().expect("Systray initialization failed");
@@ -402,9 +1194,7 @@ fn run_controller() {
     
         let tx_clone = tx.clone();
         if let Err(e) = app.add_menu_item("Show Settings", move |_| -> Result<(), MenuError> {
-            if let Some(visible) = WINDOW_VISIBLE.get() {
-                *visible.lock().unwrap() = true;
-            }
+            show_settings();
             tx_clone.send(TrayAction::ShowSettings)
                 .map_err(|e| MenuError(e.to_string()))?;
             Ok(())
@@ -412,19 +1202,52 @@ fn run_controller() {
             eprintln!("Failed to add Show Settings menu item: {}", e);
         }
     
+        // The tray can't change its menu once built, so each entry stands for a position in the
+        // profile list and looks up the profile there when clicked; renames still apply the
+        // right profile, and entries past the end of a shrunk list do nothing
+        for (index, profile) in controller::config().profiles.into_iter().enumerate() {
+            if let Err(e) = app.add_menu_item(&format!("Profile: {}", profile.name), move |_| -> Result<(), MenuError> {
+                let name = controller::config().profiles.into_iter().nth(index).map(|p| p.name)
+                    .ok_or_else(|| MenuError("that profile was deleted".to_owned()))?;
+                controller::use_profile(&name).map_err(|e| MenuError(e.to_string()))?;
+                refresh_ui();
+                Ok(())
+            }) {
+                eprintln!("Failed to add profile menu item: {}", e);
+            }
+        }
+
+        for &minutes in controller::PAUSE_MINUTES {
+            if let Err(e) = app.add_menu_item(&pause_label(minutes), move |_| -> Result<(), MenuError> {
+                controller::pause_for(Duration::from_secs(minutes as u64 * 60)).map_err(|e| MenuError(e.to_string()))?;
+                refresh_ui();
+                Ok(())
+            }) {
+                eprintln!("Failed to add pause menu item: {}", e);
+            }
+        }
+        if let Err(e) = app.add_menu_item("Pause until sunrise", |_| -> Result<(), MenuError> {
+            controller::pause_until_sunrise().map_err(|e| MenuError(e.to_string()))?;
+            refresh_ui();
+            Ok(())
+        }) {
+            eprintln!("Failed to add pause menu item: {}", e);
+        }
+        if let Err(e) = app.add_menu_item("Resume", |_| -> Result<(), MenuError> {
+            controller::resume();
+            refresh_ui();
+            Ok(())
+        }) {
+            eprintln!("Failed to add resume menu item: {}", e);
+        }
+
         let tx_clone = tx.clone();
         if let Err(e) = app.add_menu_item("Exit", move |_| -> Result<(), MenuError> {
-            kill_processes_by_name("redshift.exe");
-            
-            thread::sleep(std::time::Duration::from_millis(100));
-            
             tx_clone.send(TrayAction::Exit)
                 .map_err(|e| MenuError(e.to_string()))?;
             
-            std::process::exit(0);
-            
-            #[allow(unreachable_code)]
-            Ok(())
+            // Only our own overlay, and give it the chance to fade out first
+            controller::quit();
         }) {
             eprintln!("Failed to add Exit menu item: {}", e);
         }
@@ -432,6 +1255,20 @@ fn run_controller() {
         let _ = app.wait_for_message();
     });
 
+    rx
+}
+
+// "Pause 15 minutes", "Pause 1 hour"
+fn pause_label(minutes: u32) -> String {
+    match minutes {
+        60 => "Pause 1 hour".to_owned(),
+        _ => format!("Pause {} minutes", minutes),
+    }
+}
+
+// Bottom right of the primary screen, above the taskbar
+#[cfg(target_os = "windows")]
+fn initial_window_pos(window_width: f32, window_height: f32) -> Option<egui::Pos2> {
     // Get screen dimensions using WinAPI
     let screen_width;
     let screen_height;
@@ -443,42 +1280,33 @@ fn run_controller() {
         screen_height = GetSystemMetrics(SM_CYSCREEN);
     }
 
-    let _window_width = 270.0;
-    let _window_height = 150.0;
-    
     // Calculate position (bottom right, above taskbar)
-    let x = (screen_width as f32) - _window_width - right_margin;  // Move left by right_margin
-    let y = (screen_height as f32) - _window_height - taskbar_height as f32;  // Raise higher
-
-    let options = NativeOptions {
-        initial_window_size: Some(egui::vec2(_window_width, _window_height)),
-        initial_window_pos: Some(egui::pos2(x, y)),
-        resizable: false,
-        decorated: true,
-        transparent: true,
-        always_on_top: true,
-        min_window_size: Some(egui::vec2(_window_width, _window_height)),
-        max_window_size: Some(egui::vec2(_window_width, _window_height)),
-        icon_data: Some(load_icon()),
-        ..Default::default()
-    };
-
-    let app_creator: Box<dyn FnOnce(&eframe::CreationContext) -> Box<dyn eframe::App>> = 
-        Box::new(|cc| Box::new(ControllerApp::new(cc)));
+    let x = (screen_width as f32) - window_width - right_margin;  // Move left by right_margin
+    let y = (screen_height as f32) - window_height - taskbar_height as f32;  // Raise higher
+    Some(egui::pos2(x, y))
+}
 
-    eframe::run_native(
-        "RedShift Controller",
-        options,
-        app_creator
-    ).expect("Failed to run eframe");
+// Leave placement to the window manager
+#[cfg(not(target_os = "windows"))]
+fn initial_window_pos(_window_width: f32, _window_height: f32) -> Option<egui::Pos2> {
+    None
 }
 
 fn main() {
     let args: Vec<String> = env::args().collect();
     
+    // Internal: the controller starts us this way to draw the overlay
     if args.len() > 1 && args[1] == "--overlay" {
         overlay::run();
-    } else {
-        run_controller();
+        return;
+    }
+
+    if args.len() > 1 {
+        cli::attach_console();
+    }
+    let cli = cli::Cli::parse();
+    match cli.command {
+        Some(command) => std::process::exit(cli::run(command)),
+        None => run_controller(cli.opacity),
     }
 }
\ No newline at end of file
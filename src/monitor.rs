@@ -0,0 +1,98 @@
+// Per-monitor settings: a monitor can be left untinted (a calibrated reference screen, say)
+// or get its own intensity and color instead of the global ones.
+//
+// Monitors are keyed by name plus position and size. Names alone aren't unique (two identical
+// screens, or a generic name like "Unknown monitor"), but they do survive rearranging the
+// desktop, so a name-only match is the fallback when nothing matches exactly.
+
+use serde::{Serialize, Deserialize};
+use crate::backend::Output;
+use crate::overlay::{OverlayConfig, TintMode};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct MonitorSettings {
+    pub name: String,
+    pub position: (i32, i32),
+    pub size: (u32, u32),
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    // None follows the global setting
+    #[serde(default)]
+    pub opacity: Option<u8>,
+    #[serde(default)]
+    pub tint_mode: Option<TintMode>,
+    #[serde(default)]
+    pub color_temperature: Option<u32>,
+    #[serde(default)]
+    pub tint_color: Option<[u8; 3]>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl MonitorSettings {
+    // Follows the global settings until changed
+    pub fn new(output: &Output) -> Self {
+        Self {
+            name: output.name.clone(),
+            position: output.position,
+            size: output.size,
+            enabled: true,
+            opacity: None,
+            tint_mode: None,
+            color_temperature: None,
+            tint_color: None,
+        }
+    }
+
+    pub fn has_overrides(&self) -> bool {
+        self.opacity.is_some() || self.tint_mode.is_some() || self.color_temperature.is_some() || self.tint_color.is_some()
+    }
+
+    // Same as having no entry at all
+    pub fn is_default(&self) -> bool {
+        self.enabled && !self.has_overrides()
+    }
+
+    // The global config with this monitor's overrides on top
+    pub fn apply_to(&self, config: &mut OverlayConfig) {
+        if let Some(opacity) = self.opacity {
+            config.opacity = opacity;
+        }
+        if let Some(mode) = self.tint_mode {
+            config.tint_mode = mode;
+        }
+        if let Some(temperature) = self.color_temperature {
+            config.color_temperature = temperature;
+        }
+        if let Some(tint_color) = self.tint_color {
+            config.tint_color = tint_color;
+        }
+    }
+
+    // Short name for the UI; Windows names monitors `\\.\DISPLAY1` and so on
+    pub fn label(&self) -> String {
+        let name = self.name.strip_prefix("\\\\.\\").unwrap_or(&self.name);
+        format!("{} {}x{}", name, self.size.0, self.size.1)
+    }
+}
+
+// Index of the entry for a monitor: an exact match, or else the only entry with its name
+pub fn index_of(monitors: &[MonitorSettings], name: &str, position: (i32, i32), size: (u32, u32)) -> Option<usize> {
+    monitors
+        .iter()
+        .position(|monitor| monitor.name == name && monitor.position == position && monitor.size == size)
+        .or_else(|| {
+            let mut by_name = monitors.iter().enumerate().filter(|(_, monitor)| monitor.name == name);
+            match (by_name.next(), by_name.next()) {
+                (Some((index, _)), None) => Some(index),
+                _ => None,
+            }
+        })
+}
+
+pub fn find<'a>(monitors: &'a [MonitorSettings], output: &Output) -> Option<&'a MonitorSettings> {
+    index_of(monitors, &output.name, output.position, output.size).map(|index| &monitors[index])
+}
@@ -1,43 +1,122 @@
-use pixels::{Pixels, SurfaceTexture};
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU8, Ordering};
 use std::collections::HashMap;
-use std::sync::atomic::{AtomicU8, Ordering};
+use std::sync::Mutex;
 use std::thread;
-use std::time::Duration;
+use std::time::{Duration, Instant, SystemTime};
 use std::fs;
+use std::io::BufReader;
+use std::net::TcpStream;
 use std::path::{PathBuf, Path};
 use serde::{Serialize, Deserialize};
-use winit::{
-    dpi::{LogicalPosition, LogicalSize},
-    event::{Event, WindowEvent},
-    event_loop::{ControlFlow, EventLoop},
-    monitor::MonitorHandle,
-    window::{WindowBuilder, WindowId},
-};
-use winit::platform::windows::WindowExtWindows;
-
-use winapi::{
-    shared::windef::HWND,
-    um::winuser::{
-        GetWindowLongW, SetWindowLongW, SetWindowPos, GWL_EXSTYLE, SWP_NOMOVE, SWP_NOSIZE,
-        SWP_NOACTIVATE, WS_EX_LAYERED, WS_EX_TRANSPARENT, WS_EX_TOOLWINDOW, WS_EX_TOPMOST,
-        WS_EX_NOACTIVATE, SetLayeredWindowAttributes, LWA_ALPHA, HWND_TOPMOST,
-    },
-};
-
-// Constants for default red color and opacity
-const DEFAULT_RED: u8 = 255;
-const DEFAULT_GREEN: u8 = 0;
-const DEFAULT_BLUE: u8 = 0;
+use crate::backend::{Output, OutputId};
+use crate::color::{self, Tint};
+use crate::config;
+use crate::fade::{Fade, FadeConfig};
+use crate::fullscreen::FullscreenConfig;
+use crate::hotkey::HotkeyConfig;
+use crate::monitor::{self, MonitorSettings};
+use crate::profile::{self, Profile};
+use crate::rules::{AppRule, RuleEffect};
+use crate::ipc::{self, Command, OverlayMessage, OverlayStatus, Request};
+use crate::schedule::{self, ScheduleConfig};
+
+// Pure red until the user picks another color for intensity mode
+const DEFAULT_TINT_COLOR: [u8; 3] = [255, 0, 0];
 static CURRENT_ALPHA: AtomicU8 = AtomicU8::new(100);
+static CURRENT_COLOR: AtomicU32 = AtomicU32::new(pack_rgb(DEFAULT_TINT_COLOR));
+// How much of CURRENT_ALPHA the solar schedule lets through, 255 = all of it
+static SCHEDULE_LEVEL: AtomicU8 = AtomicU8::new(255);
+// The same for dimming, which follows its own curve
+static DIM_LEVEL: AtomicU8 = AtomicU8::new(255);
+// The overlay's own view of the settings, fed by the config file and the IPC channel
+static CONFIG: Mutex<Option<OverlayConfig>> = Mutex::new(None);
+// Set once the overlay was disabled: fade out, then exit
+static FADING_OUT: AtomicBool = AtomicBool::new(false);
+// Paused by the controller: fade out but keep running
+static PAUSED: AtomicBool = AtomicBool::new(false);
+// Set by the backend to wake its loop up; it sleeps while the tint is constant
+static WAKER: Mutex<Option<Box<dyn Fn() + Send>>> = Mutex::new(None);
+// What the backend currently drives, reported to the controller
+static OUTPUTS: Mutex<Vec<Output>> = Mutex::new(Vec::new());
+// What the rule for the app in the foreground does, as worked out by the controller
+static RULE_EFFECT: Mutex<Option<RuleEffect>> = Mutex::new(None);
+// Write half of the IPC connection, shared by acks and unprompted status updates
+static IPC_WRITER: Mutex<Option<TcpStream>> = Mutex::new(None);
+
+// Which value the controller slider edits, and therefore how the tint color is picked
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TintMode {
+    // Layer of `tint_color` (red unless changed), slider controls its intensity
+    #[default]
+    Intensity,
+    // Blackbody tint, slider controls the color temperature
+    Temperature,
+}
 
-#[derive(Serialize, Deserialize, Default)]
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(deny_unknown_fields)]
 pub struct OverlayConfig {
+    // Schema version, see config.rs
+    #[serde(default)]
+    pub version: u32,
     #[serde(default = "default_opacity")]
     pub opacity: u8,
     #[serde(default)]
+    pub tint_mode: TintMode,
+    #[serde(default = "default_color_temperature")]
+    pub color_temperature: u32,
+    // Layer color in intensity mode
+    #[serde(default = "default_tint_color")]
+    pub tint_color: [u8; 3],
+    // Percent, darkens the screen on top of the tint
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+    #[serde(default)]
     pub enabled: bool,
     #[serde(default)]
     pub launch_on_startup: bool,
+    #[serde(default)]
+    pub schedule: ScheduleConfig,
+    #[serde(default)]
+    pub fade: FadeConfig,
+    #[serde(default = "profile::default_profiles")]
+    pub profiles: Vec<Profile>,
+    // Applied when the controller starts
+    #[serde(default)]
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub monitors: Vec<MonitorSettings>,
+    #[serde(default)]
+    pub hotkeys: HotkeyConfig,
+    #[serde(default)]
+    pub fullscreen: FullscreenConfig,
+    // Per-application rules, first match wins
+    #[serde(default)]
+    pub rules: Vec<AppRule>,
+}
+
+impl Default for OverlayConfig {
+    fn default() -> Self {
+        Self {
+            version: config::CURRENT_VERSION,
+            opacity: default_opacity(),
+            tint_mode: TintMode::default(),
+            color_temperature: default_color_temperature(),
+            tint_color: default_tint_color(),
+            brightness: default_brightness(),
+            enabled: false,
+            launch_on_startup: false,
+            schedule: ScheduleConfig::default(),
+            fade: FadeConfig::default(),
+            profiles: profile::default_profiles(),
+            default_profile: None,
+            monitors: Vec::new(),
+            hotkeys: HotkeyConfig::default(),
+            fullscreen: FullscreenConfig::default(),
+            rules: Vec::new(),
+        }
+    }
 }
 
 // Add default value functions
@@ -45,21 +124,64 @@ fn default_opacity() -> u8 {
     90 // Default opacity value now starts at minimum allowed
 }
 
+fn default_color_temperature() -> u32 {
+    3400 // Roughly a warm incandescent bulb
+}
+
+pub fn default_tint_color() -> [u8; 3] {
+    DEFAULT_TINT_COLOR
+}
+
+pub fn default_brightness() -> u8 {
+    color::MAX_BRIGHTNESS
+}
+
 // Add a function to clamp opacity values
 fn clamp_opacity(opacity: u8) -> u8 {
     opacity.clamp(90, 200)
 }
 
+const fn pack_rgb(rgb: [u8; 3]) -> u32 {
+    ((rgb[0] as u32) << 16) | ((rgb[1] as u32) << 8) | rgb[2] as u32
+}
+
+fn unpack_rgb(packed: u32) -> [u8; 3] {
+    [(packed >> 16) as u8, (packed >> 8) as u8, packed as u8]
+}
+
+// Alpha actually painted: the configured intensity scaled by the schedule
+fn effective_alpha() -> u8 {
+    let alpha = CURRENT_ALPHA.load(Ordering::Relaxed) as u16;
+    let level = SCHEDULE_LEVEL.load(Ordering::Relaxed) as u16;
+    (alpha * level / 255) as u8
+}
+
+// Resolve the color and alpha the overlay should paint for a config
+pub fn tint_for(config: &OverlayConfig) -> ([u8; 3], u8) {
+    let opacity = clamp_opacity(config.opacity);
+    match config.tint_mode {
+        TintMode::Intensity => (config.tint_color, opacity),
+        TintMode::Temperature => {
+            // 6500K is daylight, so the layer fades out completely there
+            let strength = color::temperature_strength(config.color_temperature);
+            (
+                color::kelvin_to_rgb(config.color_temperature),
+                (opacity as f32 * strength).round() as u8,
+            )
+        }
+    }
+}
+
+// The per-user settings folder, so an installed binary doesn't need a writable program folder;
+// next to the executable when the environment doesn't say where that is
 pub fn config_path() -> PathBuf {
-    let path = if let Ok(local_app_data) = std::env::var("LOCALAPPDATA") {
-        PathBuf::from(local_app_data)
-    } else {
+    let path = settings_base_dir().unwrap_or_else(|| {
         std::env::current_exe()
             .unwrap_or_else(|_| PathBuf::from("."))
             .parent()
             .unwrap_or_else(|| Path::new("."))
             .to_path_buf()
-    };
+    });
     
     let mut config_path = path;
     config_path.push("RedShift");
@@ -73,183 +195,552 @@ pub fn config_path() -> PathBuf {
     config_path
 }
 
-fn watch_opacity_changes() {
-    thread::spawn(|| {
-        let config_path = config_path();
-        let mut last_opacity = CURRENT_ALPHA.load(Ordering::Relaxed);
-        println!("Starting opacity watcher with initial opacity: {}", last_opacity);
+#[cfg(target_os = "windows")]
+fn settings_base_dir() -> Option<PathBuf> {
+    std::env::var_os("LOCALAPPDATA").map(PathBuf::from)
+}
+
+#[cfg(not(target_os = "windows"))]
+fn settings_base_dir() -> Option<PathBuf> {
+    config::xdg_config_home()
+}
+
+// Make the overlay follow a (new) config: tint, schedule, fade and enabled state
+fn apply_config(config: OverlayConfig) {
+    let (new_color, new_opacity) = tint_for(&config);
+    let last_opacity = CURRENT_ALPHA.swap(new_opacity, Ordering::Relaxed);
+    if new_opacity != last_opacity {
+        println!("Opacity changed: {} -> {}", last_opacity, new_opacity);
+    }
+    let last_color = unpack_rgb(CURRENT_COLOR.swap(pack_rgb(new_color), Ordering::Relaxed));
+    if new_color != last_color {
+        println!("Color changed: {:?} -> {:?}", last_color, new_color);
+    }
+    let last_brightness = CONFIG.lock().unwrap().as_ref().map(|config| config.brightness);
+    if let Some(last_brightness) = last_brightness.filter(|last| *last != config.brightness) {
+        println!("Brightness changed: {}% -> {}%", last_brightness, config.brightness);
+    }
+    if !config.enabled && !FADING_OUT.swap(true, Ordering::Relaxed) {
+        println!("Overlay disabled, fading out");
+    }
+    *CONFIG.lock().unwrap() = Some(config);
+    wake();
+}
+
+pub fn set_waker(waker: impl Fn() + Send + 'static) {
+    *WAKER.lock().unwrap() = Some(Box::new(waker));
+}
+
+// Something that feeds into the tint changed, have the backend compute a new frame
+fn wake() {
+    if let Some(waker) = WAKER.lock().unwrap().as_ref() {
+        waker();
+    }
+}
+
+fn current_config() -> OverlayConfig {
+    CONFIG.lock().unwrap().clone().unwrap_or_default()
+}
+
+// Re-read config.json whenever it changes on disk. With an IPC connection the controller
+// pushes live changes itself, so this only needs to catch hand edits and can poll slowly.
+// `last_file` is the file as last read, to tell which settings an edit actually touched.
+fn watch_opacity_changes(
+    config_path: PathBuf,
+    mut last_file: OverlayConfig,
+    mut last_modified: Option<SystemTime>,
+    interval: Duration,
+) {
+    thread::spawn(move || {
+        println!("Starting config watcher with initial opacity: {}", CURRENT_ALPHA.load(Ordering::Relaxed));
 
         loop {
-            thread::sleep(Duration::from_millis(100)); // Adjust the frequency as needed
-            if let Ok(config_str) = fs::read_to_string(&config_path) {
-                if let Ok(config) = serde_json::from_str::<OverlayConfig>(&config_str) {
-                    let new_opacity = clamp_opacity(config.opacity);
-                    if new_opacity != last_opacity {
-                        println!("Opacity changed: {} -> {}", last_opacity, new_opacity);
-                        CURRENT_ALPHA.store(new_opacity, Ordering::Relaxed);
-                        last_opacity = new_opacity;
+            let modified = fs::metadata(&config_path).and_then(|meta| meta.modified()).ok();
+            if modified.is_some() && modified != last_modified {
+                match config::load(&config_path) {
+                    Ok(Some(file)) => {
+                        apply_config(merge_file_changes(&current_config(), &last_file, &file));
+                        last_file = file;
                     }
+                    Ok(None) => {}
+                    // Keep the current settings; the controller reports and backs up bad files
+                    Err(e) => eprintln!("Ignoring invalid config {}: {}", config_path.display(), e),
                 }
+                last_modified = modified;
             }
+            thread::sleep(interval);
         }
     });
 }
 
-pub fn run() {
-    watch_opacity_changes();
-    let event_loop = EventLoop::new();
-    let monitors: Vec<MonitorHandle> = event_loop.available_monitors().collect();
-    let mut windows: HashMap<WindowId, (winit::window::Window, Pixels)> = HashMap::new();
+// Take the settings that changed in the file and keep the rest as they are now. A save that only
+// touches, say, the schedule mustn't undo an opacity set over IPC that hasn't been saved yet.
+fn merge_file_changes(current: &OverlayConfig, before: &OverlayConfig, after: &OverlayConfig) -> OverlayConfig {
+    let to_map = |config: &OverlayConfig| match serde_json::to_value(config) {
+        Ok(serde_json::Value::Object(map)) => Some(map),
+        _ => None,
+    };
+    let (Some(mut merged), Some(before), Some(changed)) = (to_map(current), to_map(before), to_map(after)) else {
+        return after.clone();
+    };
+    for (key, value) in changed {
+        if before.get(&key) != Some(&value) {
+            merged.insert(key, value);
+        }
+    }
+    serde_json::from_value(serde_json::Value::Object(merged)).unwrap_or_else(|e| {
+        eprintln!("Failed to merge config changes: {}", e);
+        current.clone()
+    })
+}
 
-    for monitor in monitors {
-        create_overlay_window(&event_loop, &monitor, &mut windows);
+fn overlay_status() -> OverlayStatus {
+    OverlayStatus {
+        opacity: effective_alpha(),
+        rgb: unpack_rgb(CURRENT_COLOR.load(Ordering::Relaxed)),
+        paused: PAUSED.load(Ordering::Relaxed),
+        shutting_down: FADING_OUT.load(Ordering::Relaxed),
+        outputs: OUTPUTS.lock().unwrap().clone(),
     }
+}
 
-    event_loop.run(move |event, _, control_flow| {
-        *control_flow = ControlFlow::Poll;
+fn send_to_controller(message: &OverlayMessage) {
+    let mut writer = IPC_WRITER.lock().unwrap();
+    if let Some(stream) = writer.as_mut() {
+        if let Err(e) = ipc::write_message(stream, message) {
+            eprintln!("Failed to send IPC message: {}", e);
+            *writer = None;
+        }
+    }
+}
 
-        match event {
-            Event::WindowEvent { event, window_id, .. } => match event {
-                WindowEvent::CloseRequested => {
-                    *control_flow = ControlFlow::Exit;
-                }
-                WindowEvent::Resized(size) => {
-                    if let Some((_window, pixels)) = windows.get_mut(&window_id) {
-                        pixels.resize_surface(size.width, size.height).unwrap_or_else(|e| {
-                            eprintln!("Failed to resize surface: {}", e);
-                        });
-                    }
-                }
-                _ => (),
-            },
-            Event::MainEventsCleared => {
-                for (_id, (window, pixels)) in windows.iter_mut() {
-                    let current_opacity = CURRENT_ALPHA.load(Ordering::Relaxed);
-                    
-                    let frame = pixels.frame_mut();
-                    for pixel in frame.chunks_exact_mut(4) {
-                        pixel.copy_from_slice(&[
-                            DEFAULT_RED,
-                            DEFAULT_GREEN,
-                            DEFAULT_BLUE,
-                            current_opacity
-                        ]);
-                    }
-                    
-                    if let Err(e) = pixels.render() {
-                        eprintln!("Failed to render pixels: {}", e);
-                    }
+fn handle_command(command: Command) {
+    match command {
+        Command::SetOpacity { opacity } => {
+            let mut config = current_config();
+            config.opacity = opacity;
+            apply_config(config);
+        }
+        Command::SetColor { tint_mode, color_temperature, tint_color } => {
+            let mut config = current_config();
+            config.tint_mode = tint_mode;
+            config.color_temperature = color_temperature;
+            config.tint_color = tint_color;
+            apply_config(config);
+        }
+        Command::SetBrightness { brightness } => {
+            let mut config = current_config();
+            config.brightness = brightness;
+            apply_config(config);
+        }
+        Command::Pause => {
+            println!("Overlay paused");
+            PAUSED.store(true, Ordering::Relaxed);
+        }
+        Command::Resume => {
+            println!("Overlay resumed");
+            PAUSED.store(false, Ordering::Relaxed);
+        }
+        Command::Shutdown => {
+            println!("Shutdown requested, fading out");
+            FADING_OUT.store(true, Ordering::Relaxed);
+        }
+        Command::SetMonitors { monitors } => {
+            let mut config = current_config();
+            config.monitors = monitors;
+            apply_config(config);
+        }
+        Command::SetRuleEffect { effect } => {
+            println!("App rule effect: {:?}", effect);
+            *RULE_EFFECT.lock().unwrap() = effect;
+        }
+        Command::Status => {}
+    }
+    wake();
+}
 
-                    unsafe {
-                        let hwnd = window.hwnd() as HWND;
-                        SetLayeredWindowAttributes(
-                            hwnd,
-                            0,
-                            current_opacity,
-                            LWA_ALPHA
-                        );
-
-                        // Keep window on top
-                        SetWindowPos(
-                            hwnd,
-                            HWND_TOPMOST,
-                            0,
-                            0,
-                            0,
-                            0,
-                            SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
-                        );
-                    }
+// Serve commands from the controller; losing the controller means nobody can turn us off, so exit
+fn listen_for_commands(stream: TcpStream) {
+    thread::spawn(move || {
+        match stream.try_clone() {
+            Ok(writer) => *IPC_WRITER.lock().unwrap() = Some(writer),
+            Err(e) => {
+                eprintln!("Failed to clone IPC stream: {}", e);
+                return;
+            }
+        }
+        let mut reader = BufReader::new(stream);
 
-                    window.request_redraw();
+        loop {
+            match ipc::read_message::<Request>(&mut reader) {
+                Ok(Some(request)) => {
+                    handle_command(request.command);
+                    send_to_controller(&OverlayMessage::Ack { id: request.id, status: overlay_status() });
+                }
+                Ok(None) => {
+                    println!("Controller closed the IPC connection, fading out");
+                    break;
+                }
+                Err(e) => {
+                    eprintln!("IPC connection failed: {}", e);
+                    break;
                 }
             }
-            Event::RedrawRequested(_) => {
-                // Handled in MainEventsCleared
-            }
-            _ => (),
         }
+        FADING_OUT.store(true, Ordering::Relaxed);
+        wake();
     });
 }
 
-fn create_overlay_window(
-    event_loop: &EventLoop<()>,
-    monitor: &MonitorHandle,
-    windows: &mut HashMap<WindowId, (winit::window::Window, Pixels)>,
-) {
-    let size = monitor.size();
-    let position = monitor.position();
+// What the backend should do this frame
+pub enum Frame {
+    // Tint per output; outputs that aren't listed are left alone
+    Paint(Vec<(OutputId, Tint)>),
+    // Fade out finished, tear down and exit
+    Exit,
+}
 
-    let mut window_builder = WindowBuilder::new()
-        .with_title("Red Overlay")
-        .with_inner_size(LogicalSize::new(size.width as f64, size.height as f64))
-        .with_position(LogicalPosition::new(position.x as f64, position.y as f64))
-        .with_decorations(false)
-        .with_transparent(true);
+// Turns the shared state (config, per-monitor settings, schedule, pause, shutdown) into the
+// tint each output should show right now, fading between targets. Backends call `frame` from
+// their own loops.
+pub struct TintDriver {
+    fades: HashMap<OutputId, Fade>,
+    // Fade-in for the outputs there at startup; ones plugged in later use the manual fade
+    first_fade_in: Option<Duration>,
+    // Output names to tint; empty means all of them
+    monitors: Vec<String>,
+    // Outputs as last reported to the controller
+    reported: Vec<Output>,
+    // Outputs a fullscreen window currently covers
+    fullscreen: Vec<OutputId>,
+}
 
-    #[cfg(target_os = "windows")]
-    {
-        use winit::platform::windows::WindowBuilderExtWindows;
-        window_builder = window_builder.with_skip_taskbar(true);
+impl TintDriver {
+    pub fn new(fade_in: Duration, monitors: Vec<String>) -> Self {
+        Self {
+            fades: HashMap::new(),
+            first_fade_in: Some(fade_in),
+            monitors,
+            reported: Vec::new(),
+            fullscreen: Vec::new(),
+        }
     }
 
-    let window = window_builder.build(event_loop).expect("Failed to build window");
+    // Called by the backend after checking for fullscreen windows
+    pub fn set_fullscreen(&mut self, outputs: Vec<OutputId>) {
+        if outputs != self.fullscreen {
+            println!("Fullscreen window on outputs: {:?}", outputs);
+            self.fullscreen = outputs;
+        }
+    }
 
+    // Whether the overlay should stay out of the way of a fullscreen window on this output,
+    // rather than being pushed back on top of it
     #[cfg(target_os = "windows")]
-    {
-        let hwnd = window.hwnd() as HWND;
-        let ex_style = unsafe { GetWindowLongW(hwnd, GWL_EXSTYLE) } as u32;
-        let new_ex_style = ex_style
-            | WS_EX_LAYERED
-            | WS_EX_TRANSPARENT
-            | WS_EX_TOOLWINDOW
-            | WS_EX_TOPMOST
-            | WS_EX_NOACTIVATE;
-
-        unsafe {
-            SetWindowLongW(hwnd, GWL_EXSTYLE, new_ex_style as i32);
-            SetLayeredWindowAttributes(hwnd, 0, CURRENT_ALPHA.load(Ordering::Relaxed), LWA_ALPHA);
-            
-            // Keep window on top
-            SetWindowPos(
-                hwnd,
-                HWND_TOPMOST,
-                0,
-                0,
-                0,
-                0,
-                SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
-            );
-        }
-
-        // Store the HWND value for the background thread
-        let hwnd_raw = hwnd as isize;
-        thread::spawn(move || {
-            loop {
-                thread::sleep(Duration::from_millis(1000)); // Adjust the frequency as needed
-                unsafe {
-                    SetWindowPos(
-                        hwnd_raw as HWND,
-                        HWND_TOPMOST,
-                        0,
-                        0,
-                        0,
-                        0,
-                        SWP_NOMOVE | SWP_NOSIZE | SWP_NOACTIVATE,
-                    );
+    pub fn yields_to_fullscreen(&self, output: OutputId) -> bool {
+        self.fullscreen.contains(&output) && current_config().fullscreen.action != crate::fullscreen::FullscreenAction::Ignore
+    }
+
+    // Whether the overlay was asked to cover this output. Windows names its monitors
+    // `\\.\DISPLAY1` and so on, which can be given with or without the prefix.
+    pub fn shows_on(&self, output_name: &str) -> bool {
+        self.monitors.is_empty() || self.monitors.iter().any(|monitor| {
+            monitor.eq_ignore_ascii_case(output_name)
+                || output_name.strip_prefix("\\\\.\\").is_some_and(|short| monitor.eq_ignore_ascii_case(short))
+        })
+    }
+
+    pub fn frame(&mut self, now: Instant, outputs: &[Output]) -> Frame {
+        let config = current_config();
+        let fading_out = FADING_OUT.load(Ordering::Relaxed);
+        let hidden = fading_out || PAUSED.load(Ordering::Relaxed);
+        let first_fade_in = self.first_fade_in.take();
+        let rule_effect = *RULE_EFFECT.lock().unwrap();
+
+        self.report_outputs(outputs);
+        self.fades.retain(|id, _| outputs.iter().any(|output| output.id == *id));
+
+        let mut tints = Vec::new();
+        let shown: Vec<&Output> = outputs.iter().filter(|output| self.shows_on(&output.name)).collect();
+        for output in shown {
+            let target = output_target(&config, output, rule_effect, hidden, self.fullscreen.contains(&output.id));
+            let mut duration = config.fade.manual_duration();
+            let fade = self.fades.entry(output.id).or_insert_with(|| {
+                // New outputs start out transparent and fade in
+                duration = first_fade_in.unwrap_or(duration);
+                Fade::new(Tint::new(target.rgb, 0))
+            });
+            if target != fade.target() {
+                fade.retarget(target, duration, config.fade.easing, now);
+            }
+            tints.push((output.id, fade.value(now)));
+        }
+
+        if fading_out && !self.is_animating(now) {
+            println!("Fade out finished, closing overlay");
+            return Frame::Exit;
+        }
+
+        Frame::Paint(tints)
+    }
+
+    // Still fading, so the backend should keep producing frames
+    pub fn is_animating(&self, now: Instant) -> bool {
+        self.fades.values().any(|fade| fade.is_running(now))
+    }
+
+    // Let the controller know which monitors there are, for the per-monitor settings
+    fn report_outputs(&mut self, outputs: &[Output]) {
+        if self.reported == outputs {
+            return;
+        }
+        self.reported = outputs.to_vec();
+        *OUTPUTS.lock().unwrap() = self.reported.clone();
+        send_to_controller(&OverlayMessage::Status { status: overlay_status() });
+    }
+}
+
+// Global settings with the monitor's own and then the app rule's on top, scaled by the
+// schedule and fullscreen handling. Dimming goes through the same steps as the tint.
+fn output_target(config: &OverlayConfig, output: &Output, rule: Option<RuleEffect>, hidden: bool, fullscreen: bool) -> Tint {
+    let mut config = config.clone();
+    let mut enabled = true;
+    if let Some(settings) = monitor::find(&config.monitors, output).cloned() {
+        enabled = settings.enabled;
+        settings.apply_to(&mut config);
+    }
+    if let Some(rule) = rule {
+        enabled &= !rule.disabled;
+        rule.apply_to(&mut config);
+    }
+
+    let (rgb, alpha) = tint_for(&config);
+    if hidden || !enabled {
+        return Tint::new(rgb, 0);
+    }
+    let level = SCHEDULE_LEVEL.load(Ordering::Relaxed) as u16;
+    let alpha = (alpha as u16 * level / 255) as u8;
+    let dim_level = DIM_LEVEL.load(Ordering::Relaxed) as u16;
+    let dim = (color::brightness_dim(config.brightness) as u16 * dim_level / 255) as u8;
+    if fullscreen {
+        Tint::new(rgb, config.fullscreen.scale(alpha)).with_dim(config.fullscreen.scale(dim))
+    } else {
+        Tint::new(rgb, alpha).with_dim(dim)
+    }
+}
+
+// Everything the overlay can be told on its command line. The controller passes the full set
+// so the very first frame already has the right tint; anything left out comes from the config.
+#[derive(Default, Debug)]
+struct OverlayArgs {
+    config_path: Option<PathBuf>,
+    opacity: Option<u8>,
+    tint_mode: Option<TintMode>,
+    color_temperature: Option<u32>,
+    tint_color: Option<[u8; 3]>,
+    brightness: Option<u8>,
+    fade_in: Option<Duration>,
+    // Start out transparent until resumed
+    paused: bool,
+    // `--monitor` may be repeated
+    monitors: Vec<String>,
+    ipc_port: Option<u16>,
+    ipc_token: String,
+}
+
+impl OverlayArgs {
+    fn parse(args: &[String]) -> Result<Self, String> {
+        let mut parsed = Self::default();
+        let mut args = args.iter().skip_while(|arg| *arg != "--overlay").skip(1);
+
+        while let Some(name) = args.next() {
+            let value = args.next().ok_or_else(|| format!("{} needs a value", name))?;
+            let invalid = |e: &dyn std::fmt::Display| format!("invalid value '{}' for {}: {}", value, name, e);
+
+            match name.as_str() {
+                "--config" => parsed.config_path = Some(PathBuf::from(value)),
+                "--opacity" => parsed.opacity = Some(value.parse().map_err(|e| invalid(&e))?),
+                "--tint-mode" => {
+                    parsed.tint_mode = Some(clap::ValueEnum::from_str(value, true).map_err(|e| invalid(&e))?)
                 }
+                "--temperature" => parsed.color_temperature = Some(value.parse().map_err(|e| invalid(&e))?),
+                "--color" => parsed.tint_color = Some(color::parse_hex(value).map_err(|e| invalid(&e))?),
+                "--brightness" => parsed.brightness = Some(value.parse().map_err(|e| invalid(&e))?),
+                "--fade-in" => {
+                    let seconds: f32 = value.parse().map_err(|e| invalid(&e))?;
+                    parsed.fade_in = Some(Duration::from_secs_f32(seconds.max(0.0)));
+                }
+                "--paused" => parsed.paused = value.parse().map_err(|e| invalid(&e))?,
+                "--monitor" => parsed.monitors.push(value.clone()),
+                "--ipc-port" => parsed.ipc_port = Some(value.parse().map_err(|e| invalid(&e))?),
+                "--ipc-token" => parsed.ipc_token = value.clone(),
+                _ => return Err(format!("unknown overlay argument {}", name)),
             }
-        });
+        }
+        Ok(parsed)
     }
 
-    let window_size = window.inner_size();
-    let surface_texture = SurfaceTexture::new(window_size.width, window_size.height, &window);
-    let pixels = Pixels::new(
-        window_size.width,
-        window_size.height,
-        surface_texture,
-    )
-    .expect("Failed to create Pixels");
+    // Layer the command line over the saved config
+    fn apply_to(&self, config: &mut OverlayConfig) {
+        if let Some(opacity) = self.opacity {
+            config.opacity = opacity;
+        }
+        if let Some(mode) = self.tint_mode {
+            config.tint_mode = mode;
+        }
+        if let Some(temperature) = self.color_temperature {
+            config.color_temperature = temperature;
+        }
+        if let Some(tint_color) = self.tint_color {
+            config.tint_color = tint_color;
+        }
+        if let Some(brightness) = self.brightness {
+            config.brightness = brightness;
+        }
+    }
+}
+
+fn read_config(path: &Path) -> Option<(OverlayConfig, Option<SystemTime>)> {
+    let modified = fs::metadata(path).and_then(|meta| meta.modified()).ok();
+    match config::load(path) {
+        Ok(config) => config.map(|config| (config, modified)),
+        Err(e) => {
+            eprintln!("Failed to load config {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+// Drives the overlay from sunrise/sunset, independently of the controller window
+fn watch_schedule() {
+    thread::spawn(|| {
+        let mut last_phase = None;
+
+        loop {
+            update_schedule_level(&mut last_phase);
+            thread::sleep(Duration::from_millis(1000));
+        }
+    });
+}
+
+fn update_schedule_level(last_phase: &mut Option<schedule::Phase>) {
+    let config = current_config();
+    let (level, dim_level) = if config.schedule.enabled {
+        let ramp = config.fade.schedule_minutes.map(|minutes| minutes as f64 * 60.0);
+        let (phase, night_level) = schedule::current_night_level(&config.schedule, ramp);
+        if *last_phase != Some(phase) {
+            println!("Schedule phase changed: {:?} -> {:?}", last_phase, phase);
+            *last_phase = Some(phase);
+        }
+        let dim_easing = config.fade.brightness_easing.unwrap_or(config.fade.easing);
+        (
+            (config.fade.easing.apply(night_level) * 255.0).round() as u8,
+            (dim_easing.apply(night_level) * 255.0).round() as u8,
+        )
+    } else {
+        *last_phase = None;
+        (255, 255)
+    };
+    let changed = SCHEDULE_LEVEL.swap(level, Ordering::Relaxed) != level;
+    if DIM_LEVEL.swap(dim_level, Ordering::Relaxed) != dim_level || changed {
+        wake();
+    }
+}
+
+pub fn run() {
+    let args: Vec<String> = std::env::args().collect();
+    let args = match OverlayArgs::parse(&args) {
+        Ok(args) => args,
+        Err(e) => {
+            eprintln!("Overlay: {}", e);
+            std::process::exit(2);
+        }
+    };
 
-    windows.insert(window.id(), (window, pixels));
-}
\ No newline at end of file
+    // Settle the tint before anything is drawn, so we don't flash at a default intensity
+    let config_path = args.config_path.clone().unwrap_or_else(config_path);
+    let (file, last_modified) = read_config(&config_path).unwrap_or_default();
+    let mut config = file.clone();
+    args.apply_to(&mut config);
+    // We were started to show the tint, whatever the file says
+    config.enabled = true;
+    let fade_in = args.fade_in.unwrap_or_else(|| config.fade.manual_duration());
+    PAUSED.store(args.paused, Ordering::Relaxed);
+    apply_config(config);
+    update_schedule_level(&mut None);
+
+    // Connect back to the controller if it gave us a channel, otherwise fall back to watching the file
+    let ipc_stream = args.ipc_port.and_then(|port| match ipc::connect(port, &args.ipc_token) {
+        Ok(stream) => Some(stream),
+        Err(e) => {
+            eprintln!("Failed to connect to controller on port {}: {}", port, e);
+            None
+        }
+    });
+
+    if let Some(stream) = ipc_stream {
+        listen_for_commands(stream);
+        watch_opacity_changes(config_path, file, last_modified, Duration::from_secs(2));
+    } else {
+        watch_opacity_changes(config_path, file, last_modified, Duration::from_millis(100));
+    }
+    watch_schedule();
+
+    // Let Ctrl+C / SIGTERM fade out and release the backend instead of leaving the screen tinted
+    if let Err(e) = ctrlc::set_handler(|| {
+        FADING_OUT.store(true, Ordering::Relaxed);
+        wake();
+    }) {
+        eprintln!("Failed to install shutdown handler: {}", e);
+    }
+
+    crate::backend::run(TintDriver::new(fade_in, args.monitors));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn overlay_args_accept_the_old_opacity_only_form() {
+        let args: Vec<String> = ["RedShift.exe", "--overlay", "--opacity", "150"].iter().map(|arg| arg.to_string()).collect();
+        let parsed = OverlayArgs::parse(&args).unwrap();
+        assert_eq!(parsed.opacity, Some(150));
+        assert!(parsed.ipc_port.is_none());
+        assert!(OverlayArgs::parse(&[args[0].clone(), args[1].clone(), "--opacity".to_owned()]).is_err());
+    }
+
+    #[test]
+    fn file_edits_keep_live_changes_to_other_settings() {
+        let file = OverlayConfig { opacity: 120, ..OverlayConfig::default() };
+        // Set over IPC and not saved yet
+        let live = OverlayConfig { opacity: 170, brightness: 60, ..file.clone() };
+
+        // Someone turns the schedule on by hand
+        let mut edited = file.clone();
+        edited.schedule.enabled = true;
+        edited.schedule.latitude = 52.37;
+        let merged = merge_file_changes(&live, &file, &edited);
+        assert!(merged.schedule.enabled);
+        assert_eq!(merged.schedule.latitude, 52.37);
+        assert_eq!(merged.opacity, 170);
+        assert_eq!(merged.brightness, 60);
+    }
+
+    #[test]
+    fn file_edits_win_for_the_settings_they_change() {
+        let file = OverlayConfig::default();
+        let live = OverlayConfig { opacity: 170, brightness: 60, ..file.clone() };
+
+        let edited = OverlayConfig { opacity: 110, tint_mode: TintMode::Temperature, ..file.clone() };
+        let merged = merge_file_changes(&live, &file, &edited);
+        assert_eq!(merged.opacity, 110);
+        assert_eq!(merged.tint_mode, TintMode::Temperature);
+        assert_eq!(merged.brightness, 60);
+
+        // Saving what is already live changes nothing
+        let saved = OverlayConfig { opacity: 170, brightness: 60, ..file.clone() };
+        let merged = merge_file_changes(&live, &file, &saved);
+        assert_eq!((merged.opacity, merged.brightness), (170, 60));
+    }
+}
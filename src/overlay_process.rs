@@ -0,0 +1,185 @@
+// The `--overlay` child process as seen from the controller: the process handle plus
+// its IPC link, and the shutdown protocol (ask nicely, wait for the fade, then kill).
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader};
+use std::process::{Child, ChildStderr, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::color;
+use crate::ipc::{self, OverlayLink};
+use crate::overlay::{self, OverlayConfig, TintMode};
+
+// Extra time on top of the fade before we stop waiting for a clean exit
+const SHUTDOWN_SLACK: Duration = Duration::from_secs(2);
+// Lines of overlay stderr kept around to explain a crash
+const STDERR_TAIL: usize = 20;
+
+pub struct OverlayProcess {
+    child: Child,
+    link: Option<OverlayLink>,
+    grace: Duration,
+    started: Instant,
+    stderr_tail: Arc<Mutex<VecDeque<String>>>,
+}
+
+impl OverlayProcess {
+    pub fn spawn(config: &OverlayConfig, paused: bool) -> io::Result<Self> {
+        let current_exe = std::env::current_exe()?;
+
+        let mut command = Command::new(&current_exe);
+        command
+            .arg("--overlay")
+            .arg("--config")
+            .arg(overlay::config_path())
+            .args(config_args(config, paused));
+
+        // Without a link the overlay falls back to watching config.json
+        let link = match OverlayLink::listen() {
+            Ok(link) => {
+                command
+                    .arg("--ipc-port")
+                    .arg(link.port().to_string())
+                    .arg("--ipc-token")
+                    .arg(link.token());
+                Some(link)
+            }
+            Err(e) => {
+                eprintln!("Failed to open IPC channel for overlay: {}", e);
+                None
+            }
+        };
+
+        let mut child = command.stderr(Stdio::piped()).spawn()?;
+        println!("Started overlay process {}", child.id());
+
+        let stderr_tail = Arc::new(Mutex::new(VecDeque::new()));
+        if let Some(stderr) = child.stderr.take() {
+            collect_stderr(stderr, stderr_tail.clone());
+        }
+
+        Ok(Self {
+            child,
+            link,
+            grace: config.fade.manual_duration() + SHUTDOWN_SLACK,
+            started: Instant::now(),
+            stderr_tail,
+        })
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    // Some(status) once the process has exited
+    pub fn try_wait(&mut self) -> io::Result<Option<ExitStatus>> {
+        self.child.try_wait()
+    }
+
+    // Best explanation for an exit: the panic message if there was one, else the last thing it said
+    pub fn failure_reason(&self, status: ExitStatus) -> String {
+        let tail = self.stderr_tail.lock().unwrap();
+        let lines: Vec<&str> = tail.iter().map(String::as_str).filter(|line| !line.trim().is_empty()).collect();
+
+        let detail = match lines.iter().rposition(|line| line.contains("panicked at")) {
+            // Newer toolchains print the panic message on the line after the location
+            Some(index) if index + 1 < lines.len() && lines[index].trim_end().ends_with(':') => {
+                Some(lines[index + 1].trim().to_owned())
+            }
+            Some(index) => Some(lines[index].trim().to_owned()),
+            None => lines.last().map(|line| line.trim().to_owned()),
+        };
+
+        match detail {
+            Some(detail) => format!("{} ({})", detail, status),
+            None => status.to_string(),
+        }
+    }
+
+    pub fn pid(&self) -> u32 {
+        self.child.id()
+    }
+
+    pub fn link(&self) -> Option<&OverlayLink> {
+        self.link.as_ref()
+    }
+
+    pub fn send(&self, command: ipc::Command) -> io::Result<u64> {
+        match &self.link {
+            Some(link) => link.send(command),
+            None => Err(io::Error::new(io::ErrorKind::NotConnected, "overlay has no IPC channel")),
+        }
+    }
+
+    // Ask the overlay to fade out and exit, and only kill it (this PID, nothing else)
+    // if it is still around once the grace period is over. Blocks until it's gone.
+    pub fn shutdown(mut self) {
+        let pid = self.child.id();
+        if let Err(e) = self.send(ipc::Command::Shutdown) {
+            // It still fades out by itself when the IPC connection drops or it sees
+            // `enabled: false` in the config
+            eprintln!("Failed to ask overlay {} to shut down: {}", pid, e);
+        }
+
+        let deadline = Instant::now() + self.grace;
+        while Instant::now() < deadline {
+            match self.child.try_wait() {
+                Ok(Some(status)) => {
+                    println!("Overlay process {} exited: {}", pid, status);
+                    return;
+                }
+                Ok(None) => thread::sleep(Duration::from_millis(50)),
+                Err(e) => {
+                    eprintln!("Failed to check overlay process {}: {}", pid, e);
+                    break;
+                }
+            }
+        }
+
+        println!("Overlay process {} did not exit in time, killing it", pid);
+        if let Err(e) = self.child.kill() {
+            eprintln!("Failed to kill overlay process {}: {}", pid, e);
+        }
+        let _ = self.child.wait();
+    }
+}
+
+// The settings the overlay applies before its first frame, on top of config.json
+pub fn config_args(config: &OverlayConfig, paused: bool) -> Vec<String> {
+    let tint_mode = match config.tint_mode {
+        TintMode::Intensity => "intensity",
+        TintMode::Temperature => "temperature",
+    };
+    vec![
+        "--opacity".to_owned(),
+        config.opacity.to_string(),
+        "--tint-mode".to_owned(),
+        tint_mode.to_owned(),
+        "--temperature".to_owned(),
+        config.color_temperature.to_string(),
+        "--color".to_owned(),
+        color::to_hex(config.tint_color),
+        "--brightness".to_owned(),
+        config.brightness.to_string(),
+        "--fade-in".to_owned(),
+        config.fade.manual_seconds.to_string(),
+        "--paused".to_owned(),
+        paused.to_string(),
+    ]
+}
+
+// Forward the overlay's stderr to ours while remembering the last few lines
+fn collect_stderr(stderr: ChildStderr, tail: Arc<Mutex<VecDeque<String>>>) {
+    thread::spawn(move || {
+        for line in BufReader::new(stderr).lines() {
+            let Ok(line) = line else { break };
+            eprintln!("[overlay] {}", line);
+            let mut tail = tail.lock().unwrap();
+            if tail.len() == STDERR_TAIL {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+    });
+}
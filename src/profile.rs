@@ -0,0 +1,106 @@
+// Named profiles: saved combinations of tint settings ("Reading", "Movie", ...) that can be
+// switched between from the settings window, the tray and the command line.
+
+use serde::{Serialize, Deserialize};
+use crate::overlay::{self, OverlayConfig, TintMode};
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Profile {
+    pub name: String,
+    pub tint_mode: TintMode,
+    pub opacity: u8,
+    pub color_temperature: u32,
+    #[serde(default = "overlay::default_tint_color")]
+    pub tint_color: [u8; 3],
+    // Profiles saved before brightness existed leave the screen at full brightness
+    #[serde(default = "overlay::default_brightness")]
+    pub brightness: u8,
+}
+
+#[derive(Debug)]
+pub struct ProfileError(pub String);
+
+impl std::fmt::Display for ProfileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl std::error::Error for ProfileError {}
+
+impl Profile {
+    // Snapshot of the current settings
+    pub fn from_config(name: &str, config: &OverlayConfig) -> Self {
+        Self {
+            name: name.to_owned(),
+            tint_mode: config.tint_mode,
+            opacity: config.opacity,
+            color_temperature: config.color_temperature,
+            tint_color: config.tint_color,
+            brightness: config.brightness,
+        }
+    }
+
+    pub fn apply_to(&self, config: &mut OverlayConfig) {
+        config.tint_mode = self.tint_mode;
+        config.opacity = self.opacity;
+        config.color_temperature = self.color_temperature;
+        config.tint_color = self.tint_color;
+        config.brightness = self.brightness;
+    }
+
+    // Whether the settings are exactly what this profile would set
+    pub fn matches(&self, config: &OverlayConfig) -> bool {
+        *self == Self::from_config(&self.name, config)
+    }
+}
+
+// What a fresh install (or an upgraded config without profiles) starts with
+pub fn default_profiles() -> Vec<Profile> {
+    vec![
+        Profile {
+            name: "Reading".to_owned(),
+            tint_mode: TintMode::Temperature,
+            opacity: 150,
+            color_temperature: 4500,
+            tint_color: overlay::default_tint_color(),
+            brightness: 100,
+        },
+        Profile {
+            name: "Movie".to_owned(),
+            tint_mode: TintMode::Temperature,
+            opacity: 120,
+            color_temperature: 5000,
+            tint_color: overlay::default_tint_color(),
+            brightness: 100,
+        },
+        Profile {
+            name: "Late night coding".to_owned(),
+            tint_mode: TintMode::Temperature,
+            opacity: 200,
+            color_temperature: 2700,
+            tint_color: overlay::default_tint_color(),
+            brightness: 80,
+        },
+    ]
+}
+
+// Names are matched case-insensitively so the CLI doesn't need exact spelling
+pub fn find<'a>(profiles: &'a [Profile], name: &str) -> Option<&'a Profile> {
+    profiles.iter().find(|profile| profile.name.eq_ignore_ascii_case(name.trim()))
+}
+
+pub fn check_name(profiles: &[Profile], name: &str) -> Result<String, ProfileError> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(ProfileError("profile name can't be empty".to_owned()));
+    }
+    if name.chars().count() > 40 {
+        return Err(ProfileError("profile name is longer than 40 characters".to_owned()));
+    }
+    if find(profiles, name).is_some() {
+        return Err(ProfileError(format!("a profile named '{}' already exists", name)));
+    }
+    Ok(name.to_owned())
+}
@@ -0,0 +1,292 @@
+// Per-application rules: while a matching app has the focus, the tint is switched off, set to a
+// different opacity or taken from a profile. The controller watches the foreground window,
+// picks the rule and tells the overlay; the saved settings are never touched.
+//
+// Rules are tried in order and the first match wins, so more specific rules go first.
+
+use serde::{Serialize, Deserialize};
+use crate::overlay::{OverlayConfig, TintMode};
+use crate::profile::{self, Profile};
+
+#[cfg(target_os = "windows")]
+use winapi::{
+    shared::minwindef::{DWORD, FALSE},
+    um::handleapi::CloseHandle,
+    um::processthreadsapi::{GetCurrentProcessId, OpenProcess},
+    um::winbase::QueryFullProcessImageNameW,
+    um::winnt::PROCESS_QUERY_LIMITED_INFORMATION,
+    um::winuser::{GetClassNameW, GetForegroundWindow, GetWindowThreadProcessId},
+};
+
+// What a rule's pattern is compared with
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleTarget {
+    // File name of the process, e.g. "photoshop.exe"
+    #[default]
+    Executable,
+    // Window class of the foreground window, e.g. "CASCADIA_HOSTING_WINDOW_CLASS"
+    WindowClass,
+}
+
+// Written as `"disable"`, `{ "opacity": 120 }` or `{ "profile": "Movie" }`
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    // No tint at all
+    Disable,
+    Opacity(u8),
+    Profile(String),
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct AppRule {
+    // Case-insensitive; `*` matches any run of characters
+    pub pattern: String,
+    #[serde(default)]
+    pub target: RuleTarget,
+    pub action: RuleAction,
+}
+
+impl AppRule {
+    // An empty pattern matches nothing
+    pub fn matches(&self, app: &ForegroundApp) -> bool {
+        let text = match self.target {
+            RuleTarget::Executable => &app.executable,
+            RuleTarget::WindowClass => &app.window_class,
+        };
+        let pattern = self.pattern.trim();
+        !pattern.is_empty() && !text.is_empty() && glob_match(&pattern.to_lowercase(), &text.to_lowercase())
+    }
+
+    // None for a rule pointing at a profile that no longer exists
+    pub fn effect(&self, profiles: &[Profile]) -> Option<RuleEffect> {
+        let effect = match &self.action {
+            RuleAction::Disable => RuleEffect { disabled: true, ..RuleEffect::default() },
+            RuleAction::Opacity(opacity) => RuleEffect { opacity: Some(*opacity), ..RuleEffect::default() },
+            RuleAction::Profile(name) => {
+                let profile = profile::find(profiles, name)?;
+                RuleEffect {
+                    disabled: false,
+                    opacity: Some(profile.opacity),
+                    tint_mode: Some(profile.tint_mode),
+                    color_temperature: Some(profile.color_temperature),
+                    tint_color: Some(profile.tint_color),
+                    brightness: Some(profile.brightness),
+                }
+            }
+        };
+        Some(effect)
+    }
+}
+
+// A rule resolved to what it does to the tint, as sent to the overlay
+#[derive(Serialize, Deserialize, Default, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RuleEffect {
+    pub disabled: bool,
+    pub opacity: Option<u8>,
+    pub tint_mode: Option<TintMode>,
+    pub color_temperature: Option<u32>,
+    #[serde(default)]
+    pub tint_color: Option<[u8; 3]>,
+    #[serde(default)]
+    pub brightness: Option<u8>,
+}
+
+impl RuleEffect {
+    pub fn apply_to(&self, config: &mut OverlayConfig) {
+        if let Some(opacity) = self.opacity {
+            config.opacity = opacity;
+        }
+        if let Some(mode) = self.tint_mode {
+            config.tint_mode = mode;
+        }
+        if let Some(temperature) = self.color_temperature {
+            config.color_temperature = temperature;
+        }
+        if let Some(tint_color) = self.tint_color {
+            config.tint_color = tint_color;
+        }
+        if let Some(brightness) = self.brightness {
+            config.brightness = brightness;
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Eq, Debug, Default)]
+pub struct ForegroundApp {
+    pub executable: String,
+    pub window_class: String,
+}
+
+// The first rule that matches and still does something, and what it does
+pub fn evaluate<'a>(rules: &'a [AppRule], profiles: &[Profile], app: &ForegroundApp) -> Option<(&'a AppRule, RuleEffect)> {
+    rules
+        .iter()
+        .filter(|rule| rule.matches(app))
+        .find_map(|rule| rule.effect(profiles).map(|effect| (rule, effect)))
+}
+
+// `*` matches any run of characters, everything else matches itself
+fn glob_match(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else { return false };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // No `*` at all
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
+
+pub fn supported() -> bool {
+    cfg!(target_os = "windows")
+}
+
+// Only Windows can tell us about the foreground window so far
+#[cfg(not(target_os = "windows"))]
+pub fn foreground_app() -> Option<ForegroundApp> {
+    None
+}
+
+// None while RedShift itself (the settings window) has the focus, or nothing does
+#[cfg(target_os = "windows")]
+pub fn foreground_app() -> Option<ForegroundApp> {
+    unsafe {
+        let hwnd = GetForegroundWindow();
+        if hwnd.is_null() {
+            return None;
+        }
+        let mut process_id: DWORD = 0;
+        GetWindowThreadProcessId(hwnd, &mut process_id);
+        if process_id == GetCurrentProcessId() {
+            return None;
+        }
+
+        let mut class = [0u16; 256];
+        let length = GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32);
+        let window_class = String::from_utf16_lossy(&class[..length.max(0) as usize]);
+
+        // Limited access is enough for the image name and works for elevated processes too
+        let mut executable = String::new();
+        let process = OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, FALSE, process_id);
+        if !process.is_null() {
+            let mut path = [0u16; 1024];
+            let mut size = path.len() as DWORD;
+            if QueryFullProcessImageNameW(process, 0, path.as_mut_ptr(), &mut size) != 0 {
+                let path = String::from_utf16_lossy(&path[..size as usize]);
+                executable = path.rsplit('\\').next().unwrap_or_default().to_owned();
+            }
+            CloseHandle(process);
+        }
+
+        Some(ForegroundApp { executable, window_class })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(pattern: &str, target: RuleTarget, action: RuleAction) -> AppRule {
+        AppRule { pattern: pattern.to_owned(), target, action }
+    }
+
+    fn exe(pattern: &str) -> AppRule {
+        rule(pattern, RuleTarget::Executable, RuleAction::Disable)
+    }
+
+    fn app(executable: &str, window_class: &str) -> ForegroundApp {
+        ForegroundApp { executable: executable.to_owned(), window_class: window_class.to_owned() }
+    }
+
+    fn movie() -> Profile {
+        Profile {
+            name: "Movie".to_owned(),
+            tint_mode: TintMode::Temperature,
+            opacity: 120,
+            color_temperature: 2700,
+            tint_color: [255, 0, 0],
+            brightness: 80,
+        }
+    }
+
+    #[test]
+    fn matching_ignores_case() {
+        let vlc = app("VLC.exe", "Qt5QWindowIcon");
+        assert!(exe("vlc.EXE").matches(&vlc));
+        assert!(!exe("vlc").matches(&vlc));
+    }
+
+    #[test]
+    fn wildcards_match_anywhere_in_the_pattern() {
+        let photoshop = app("Photoshop.exe", "Photoshop");
+        assert!(exe("*shop.exe").matches(&photoshop));
+        assert!(exe("photo*").matches(&photoshop));
+        assert!(exe("photo*.exe").matches(&photoshop));
+        assert!(exe("p*o*p.exe").matches(&photoshop));
+        assert!(exe("*").matches(&photoshop));
+        assert!(!exe("*paint*").matches(&photoshop));
+        assert!(!exe("photo*.dll").matches(&photoshop));
+        // The parts around a `*` can't share characters
+        assert!(!exe("photoshop*shop.exe").matches(&photoshop));
+    }
+
+    #[test]
+    fn an_empty_pattern_matches_nothing() {
+        assert!(!exe("").matches(&app("code.exe", "Chrome_WidgetWin_1")));
+        assert!(!exe("  ").matches(&app("code.exe", "Chrome_WidgetWin_1")));
+        assert!(!exe("").matches(&app("", "")));
+    }
+
+    #[test]
+    fn target_picks_executable_or_window_class() {
+        let terminal = app("WindowsTerminal.exe", "CASCADIA_HOSTING_WINDOW_CLASS");
+        assert!(rule("windowsterminal.exe", RuleTarget::Executable, RuleAction::Disable).matches(&terminal));
+        assert!(!rule("windowsterminal.exe", RuleTarget::WindowClass, RuleAction::Disable).matches(&terminal));
+        assert!(rule("cascadia*", RuleTarget::WindowClass, RuleAction::Disable).matches(&terminal));
+        assert!(!rule("cascadia*", RuleTarget::Executable, RuleAction::Disable).matches(&terminal));
+    }
+
+    #[test]
+    fn first_match_wins() {
+        let rules = vec![
+            rule("notepad.exe", RuleTarget::Executable, RuleAction::Disable),
+            rule("code.exe", RuleTarget::Executable, RuleAction::Opacity(150)),
+            rule("*", RuleTarget::Executable, RuleAction::Opacity(100)),
+        ];
+        let (matched, effect) = evaluate(&rules, &[], &app("Code.exe", "Chrome_WidgetWin_1")).unwrap();
+        assert_eq!(matched, &rules[1]);
+        assert_eq!(effect, RuleEffect { opacity: Some(150), ..RuleEffect::default() });
+
+        let (_, effect) = evaluate(&rules, &[], &app("notepad.exe", "Notepad")).unwrap();
+        assert!(effect.disabled);
+        assert!(evaluate(&rules[..2], &[], &app("explorer.exe", "CabinetWClass")).is_none());
+    }
+
+    #[test]
+    fn rule_for_a_deleted_profile_falls_through() {
+        let rules = vec![
+            rule("vlc.exe", RuleTarget::Executable, RuleAction::Profile("Cinema".to_owned())),
+            rule("vlc.exe", RuleTarget::Executable, RuleAction::Profile("Movie".to_owned())),
+        ];
+        let profiles = vec![movie()];
+        let (matched, effect) = evaluate(&rules, &profiles, &app("vlc.exe", "Qt5QWindowIcon")).unwrap();
+        assert_eq!(matched, &rules[1]);
+        assert_eq!(effect.opacity, Some(120));
+        assert_eq!(effect.tint_mode, Some(TintMode::Temperature));
+        assert_eq!(effect.brightness, Some(80));
+
+        // With the profile gone as well nothing applies
+        assert!(evaluate(&rules, &[], &app("vlc.exe", "Qt5QWindowIcon")).is_none());
+    }
+}
@@ -0,0 +1,352 @@
+// Solar schedule: works out sunrise, sunset and civil twilight from coordinates
+// and the system clock, so the overlay can follow the sun without any network access.
+// The math is the standard sunrise equation (NOAA / Wikipedia "Sunrise equation").
+
+use chrono::{DateTime, Days, Local, NaiveDate};
+use serde::{Serialize, Deserialize};
+
+// Sun altitude at which the upper limb touches the horizon, corrected for refraction
+const SUNRISE_ALTITUDE: f64 = -0.833;
+// Civil twilight ends when the sun is 6 degrees below the horizon
+const CIVIL_TWILIGHT_ALTITUDE: f64 = -6.0;
+
+const J2000: f64 = 2451545.0;
+const UNIX_EPOCH_JULIAN: f64 = 2440587.5;
+const SECONDS_PER_DAY: f64 = 86400.0;
+
+#[derive(Serialize, Deserialize, Default, Clone, PartialEq, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct ScheduleConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    // Degrees, north positive
+    #[serde(default)]
+    pub latitude: f64,
+    // Degrees, east positive
+    #[serde(default)]
+    pub longitude: f64,
+}
+
+// When the sun crosses a given altitude on one day
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Crossing {
+    // Unix timestamps (seconds) of the morning and evening crossings
+    Times { rise: f64, set: f64 },
+    // Polar day: the sun never drops below the altitude
+    AlwaysAbove,
+    // Polar night: the sun never climbs above the altitude
+    AlwaysBelow,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SolarDay {
+    pub sun: Crossing,
+    pub civil: Crossing,
+    // What it takes to work out the sun's altitude at any moment of the day
+    latitude: f64,
+    declination: f64,
+    // Unix time of solar noon
+    noon: f64,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum Phase {
+    Day,
+    Dawn,
+    Dusk,
+    Night,
+}
+
+fn to_unix(julian: f64) -> f64 {
+    (julian - UNIX_EPOCH_JULIAN) * SECONDS_PER_DAY
+}
+
+// Julian date of solar noon and the sun's declination (radians) on `date`
+fn sun_position(date: NaiveDate, longitude: f64) -> (f64, f64) {
+    let j2000_date = NaiveDate::from_ymd_opt(2000, 1, 1).unwrap();
+    let day_number = date.signed_duration_since(j2000_date).num_days() as f64;
+
+    // Mean solar noon at this longitude
+    let mean_noon = day_number - longitude / 360.0;
+
+    let mean_anomaly = (357.5291 + 0.98560028 * mean_noon).rem_euclid(360.0).to_radians();
+    let center = 1.9148 * mean_anomaly.sin()
+        + 0.02 * (2.0 * mean_anomaly).sin()
+        + 0.0003 * (3.0 * mean_anomaly).sin();
+    let ecliptic_longitude =
+        (mean_anomaly.to_degrees() + center + 180.0 + 102.9372).rem_euclid(360.0).to_radians();
+
+    let transit = J2000 + mean_noon + 0.0053 * mean_anomaly.sin()
+        - 0.0069 * (2.0 * ecliptic_longitude).sin();
+
+    let declination = (ecliptic_longitude.sin() * 23.4397_f64.to_radians().sin()).asin();
+    (transit, declination)
+}
+
+fn crossing(transit: f64, declination: f64, latitude: f64, altitude: f64) -> Crossing {
+    let latitude = latitude.to_radians();
+
+    let cos_hour_angle = (altitude.to_radians().sin() - latitude.sin() * declination.sin())
+        / (latitude.cos() * declination.cos());
+
+    if cos_hour_angle > 1.0 {
+        return Crossing::AlwaysBelow;
+    }
+    if cos_hour_angle < -1.0 {
+        return Crossing::AlwaysAbove;
+    }
+
+    let hour_angle = cos_hour_angle.acos().to_degrees();
+    Crossing::Times {
+        rise: to_unix(transit - hour_angle / 360.0),
+        set: to_unix(transit + hour_angle / 360.0),
+    }
+}
+
+pub fn solar_day(date: NaiveDate, latitude: f64, longitude: f64) -> SolarDay {
+    let (transit, declination) = sun_position(date, longitude);
+    SolarDay {
+        sun: crossing(transit, declination, latitude, SUNRISE_ALTITUDE),
+        civil: crossing(transit, declination, latitude, CIVIL_TWILIGHT_ALTITUDE),
+        latitude,
+        declination,
+        noon: to_unix(transit),
+    }
+}
+
+impl SolarDay {
+    // Degrees above the horizon at `now` (unix seconds)
+    fn altitude(&self, now: f64) -> f64 {
+        let hour_angle = ((now - self.noon) / SECONDS_PER_DAY * 360.0).to_radians();
+        let latitude = self.latitude.to_radians();
+        (latitude.sin() * self.declination.sin() + latitude.cos() * self.declination.cos() * hour_angle.cos())
+            .asin()
+            .to_degrees()
+    }
+}
+
+// Where in the day `now` (unix seconds) falls, plus how far into the night it is:
+// 0.0 in daylight, 1.0 after dusk, ramping linearly through civil twilight,
+// or over `ramp` seconds after sunset / before sunrise when one is given.
+pub fn night_level(now: f64, day: &SolarDay, ramp: Option<f64>) -> (Phase, f32) {
+    match (day.sun, day.civil) {
+        (Crossing::Times { rise, set }, Crossing::Times { rise: dawn, set: dusk }) => {
+            let (dawn, dusk) = match ramp {
+                Some(ramp) if ramp > 0.0 => (rise - ramp, set + ramp),
+                _ => (dawn, dusk),
+            };
+            if now >= rise && now <= set {
+                (Phase::Day, 0.0)
+            } else if now >= dawn && now < rise {
+                (Phase::Dawn, ((rise - now) / (rise - dawn)) as f32)
+            } else if now > set && now <= dusk {
+                (Phase::Dusk, ((now - set) / (dusk - set)) as f32)
+            } else {
+                (Phase::Night, 1.0)
+            }
+        }
+        (Crossing::AlwaysAbove, _) => (Phase::Day, 0.0),
+        (Crossing::AlwaysBelow, Crossing::AlwaysBelow) => (Phase::Night, 1.0),
+        // White nights (the sun sets but civil twilight never ends) and polar night with a
+        // twilight around noon. Twilight has no start or end time to ramp between, so follow
+        // the sun's altitude instead; `ramp` can't apply either.
+        _ => {
+            let level = ((SUNRISE_ALTITUDE - day.altitude(now)) / (SUNRISE_ALTITUDE - CIVIL_TWILIGHT_ALTITUDE))
+                .clamp(0.0, 1.0) as f32;
+            let phase = if level <= 0.0 {
+                Phase::Day
+            } else if level >= 1.0 {
+                Phase::Night
+            } else if now < day.noon {
+                Phase::Dawn
+            } else {
+                Phase::Dusk
+            };
+            (phase, level)
+        }
+    }
+}
+
+// Unix time of the first sunrise after now. Looks up to a year ahead, which only matters in
+// polar night; None if the sun doesn't rise in that time.
+pub fn next_sunrise(config: &ScheduleConfig) -> Option<f64> {
+    next_sunrise_after(Local::now(), config)
+}
+
+fn next_sunrise_after<Tz: chrono::TimeZone>(now: DateTime<Tz>, config: &ScheduleConfig) -> Option<f64> {
+    let timestamp = now.timestamp() as f64;
+    (0..=366).find_map(|offset| {
+        let date = now.date_naive().checked_add_days(Days::new(offset))?;
+        match solar_day(date, config.latitude, config.longitude).sun {
+            Crossing::Times { rise, .. } if rise > timestamp => Some(rise),
+            _ => None,
+        }
+    })
+}
+
+// Night level for the current moment on the system clock
+pub fn current_night_level(config: &ScheduleConfig, ramp: Option<f64>) -> (Phase, f32) {
+    let now = Local::now();
+    let day = solar_day(now.date_naive(), config.latitude, config.longitude);
+    night_level(now.timestamp() as f64, &day, ramp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{TimeZone, Utc};
+
+    const AMSTERDAM: (f64, f64) = (52.37, 4.90);
+    const ARKHANGELSK: (f64, f64) = (64.54, 40.54);
+    const TROMSO: (f64, f64) = (69.65, 18.96);
+    const LONGYEARBYEN: (f64, f64) = (78.22, 15.65);
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> f64 {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap().timestamp() as f64
+    }
+
+    fn assert_near(actual: f64, expected: f64) {
+        // Published tables round to the minute, and the equation is good to about one
+        assert!((actual - expected).abs() <= 120.0, "{} is more than 2 minutes from {}", actual, expected);
+    }
+
+    fn times(crossing: Crossing) -> (f64, f64) {
+        match crossing {
+            Crossing::Times { rise, set } => (rise, set),
+            other => panic!("expected rise and set times, got {:?}", other),
+        }
+    }
+
+    // Levels every minute over two local days, each minute looked up on its own local date
+    fn levels(location: (f64, f64), from: NaiveDate) -> Vec<f32> {
+        let start = from.and_hms_opt(0, 0, 0).unwrap().and_utc().timestamp() as f64 - location.1 / 15.0 * 3600.0;
+        (0..2 * 24 * 60)
+            .map(|minute| {
+                let now = start + minute as f64 * 60.0;
+                let solar_date = Utc.timestamp_opt((now + location.1 / 15.0 * 3600.0) as i64, 0).unwrap().date_naive();
+                night_level(now, &solar_day(solar_date, location.0, location.1), None).1
+            })
+            .collect()
+    }
+
+    fn assert_smooth(levels: &[f32]) {
+        for (minute, pair) in levels.windows(2).enumerate() {
+            assert!((pair[1] - pair[0]).abs() < 0.05, "level jumps from {} to {} at minute {}", pair[0], pair[1], minute);
+        }
+    }
+
+    #[test]
+    fn amsterdam_matches_published_times() {
+        let day = solar_day(date(2024, 12, 21), AMSTERDAM.0, AMSTERDAM.1);
+        // 08:48 and 16:29 CET; civil twilight 08:07 and 17:10 CET
+        let (rise, set) = times(day.sun);
+        assert_near(rise, utc(2024, 12, 21, 7, 48));
+        assert_near(set, utc(2024, 12, 21, 15, 29));
+        let (dawn, dusk) = times(day.civil);
+        assert_near(dawn, utc(2024, 12, 21, 7, 7));
+        assert_near(dusk, utc(2024, 12, 21, 16, 10));
+
+        // 05:18 and 22:06 CEST
+        let (rise, set) = times(solar_day(date(2024, 6, 21), AMSTERDAM.0, AMSTERDAM.1).sun);
+        assert_near(rise, utc(2024, 6, 21, 3, 18));
+        assert_near(set, utc(2024, 6, 21, 20, 6));
+    }
+
+    #[test]
+    fn amsterdam_levels_through_the_day() {
+        let day = solar_day(date(2024, 12, 21), AMSTERDAM.0, AMSTERDAM.1);
+        let (rise, set) = times(day.sun);
+        let (dawn, dusk) = times(day.civil);
+
+        assert_eq!(night_level(utc(2024, 12, 21, 12, 0), &day, None), (Phase::Day, 0.0));
+        assert_eq!(night_level(utc(2024, 12, 21, 2, 0), &day, None), (Phase::Night, 1.0));
+        assert_eq!(night_level(utc(2024, 12, 21, 21, 0), &day, None), (Phase::Night, 1.0));
+        let (phase, level) = night_level((set + dusk) / 2.0, &day, None);
+        assert_eq!(phase, Phase::Dusk);
+        assert!((level - 0.5).abs() < 0.01);
+        let (phase, level) = night_level((dawn + rise) / 2.0, &day, None);
+        assert_eq!(phase, Phase::Dawn);
+        assert!((level - 0.5).abs() < 0.01);
+
+        // A fixed ramp replaces civil twilight
+        let (_, level) = night_level(set + 15.0 * 60.0, &day, Some(30.0 * 60.0));
+        assert!((level - 0.5).abs() < 0.01);
+        assert_smooth(&levels(AMSTERDAM, date(2024, 12, 21)));
+    }
+
+    #[test]
+    fn polar_day_stays_untinted() {
+        let day = solar_day(date(2024, 6, 21), TROMSO.0, TROMSO.1);
+        assert_eq!(day.sun, Crossing::AlwaysAbove);
+        assert!(levels(TROMSO, date(2024, 6, 21)).iter().all(|&level| level == 0.0));
+    }
+
+    #[test]
+    fn polar_night_brightens_smoothly_around_noon() {
+        let day = solar_day(date(2024, 12, 21), TROMSO.0, TROMSO.1);
+        assert_eq!(day.sun, Crossing::AlwaysBelow);
+        let (dawn, dusk) = times(day.civil);
+
+        assert_eq!(night_level(dawn - 3600.0, &day, None), (Phase::Night, 1.0));
+        assert_eq!(night_level(dusk + 3600.0, &day, None), (Phase::Night, 1.0));
+        // The sun gets to about -3 degrees: a bit over half way out of the night, not a fixed 0.5
+        let (phase, noon_level) = night_level(day.noon, &day, None);
+        assert_eq!(phase, Phase::Dusk);
+        assert!(noon_level > 0.3 && noon_level < 0.6, "{}", noon_level);
+        let (phase, level) = night_level((dawn + day.noon) / 2.0, &day, None);
+        assert_eq!(phase, Phase::Dawn);
+        assert!(level > noon_level && level < 1.0);
+
+        let levels = levels(TROMSO, date(2024, 12, 21));
+        assert_smooth(&levels);
+        assert!(levels.iter().all(|&level| level > 0.3));
+    }
+
+    #[test]
+    fn dark_polar_night_is_all_night() {
+        let day = solar_day(date(2024, 12, 21), LONGYEARBYEN.0, LONGYEARBYEN.1);
+        assert_eq!((day.sun, day.civil), (Crossing::AlwaysBelow, Crossing::AlwaysBelow));
+        assert!(levels(LONGYEARBYEN, date(2024, 12, 21)).iter().all(|&level| level == 1.0));
+    }
+
+    #[test]
+    fn white_nights_deepen_smoothly_towards_midnight() {
+        let day = solar_day(date(2024, 6, 21), ARKHANGELSK.0, ARKHANGELSK.1);
+        assert_eq!(day.civil, Crossing::AlwaysAbove);
+        let (rise, set) = times(day.sun);
+
+        assert_eq!(night_level(day.noon, &day, None), (Phase::Day, 0.0));
+        assert!(night_level(set, &day, None).1 < 0.01);
+        assert_eq!(night_level(rise - 60.0, &day, None).0, Phase::Dawn);
+        assert_eq!(night_level(set + 60.0, &day, None).0, Phase::Dusk);
+
+        // The sun only gets to about -2 degrees, so the tint never reaches full night
+        let levels = levels(ARKHANGELSK, date(2024, 6, 20));
+        assert_smooth(&levels);
+        let deepest = levels.iter().cloned().fold(0.0, f32::max);
+        assert!(deepest > 0.1 && deepest < 0.4, "{}", deepest);
+    }
+
+    #[test]
+    fn next_sunrise_skips_ahead() {
+        let at = |location: (f64, f64), timestamp: f64| {
+            let now = Utc.timestamp_opt(timestamp as i64, 0).unwrap();
+            next_sunrise_after(now, &ScheduleConfig { enabled: true, latitude: location.0, longitude: location.1 })
+        };
+
+        // Later the same day, then the next morning
+        assert_near(at(AMSTERDAM, utc(2024, 6, 21, 1, 0)).unwrap(), utc(2024, 6, 21, 3, 18));
+        let tomorrow = at(AMSTERDAM, utc(2024, 6, 21, 12, 0)).unwrap();
+        assert!(tomorrow > utc(2024, 6, 22, 3, 0) && tomorrow < utc(2024, 6, 22, 3, 30));
+
+        // Polar night in Tromsø ends in mid-January, the midnight sun in late July
+        let after_polar_night = at(TROMSO, utc(2024, 12, 21, 12, 0)).unwrap();
+        assert!(after_polar_night > utc(2025, 1, 10, 0, 0) && after_polar_night < utc(2025, 1, 20, 0, 0));
+        let after_midnight_sun = at(TROMSO, utc(2024, 6, 21, 12, 0)).unwrap();
+        assert!(after_midnight_sun > utc(2024, 7, 18, 0, 0) && after_midnight_sun < utc(2024, 7, 28, 0, 0));
+    }
+}
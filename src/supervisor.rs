@@ -0,0 +1,280 @@
+// Keeps the overlay process alive: notices when it dies on its own, restarts it with backoff,
+// and gives up after repeated failures. The outcome is surfaced in the controller window and
+// the tray tooltip.
+
+use std::io;
+use std::process::ExitStatus;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use crate::ipc;
+use crate::overlay::OverlayConfig;
+use crate::overlay_process::OverlayProcess;
+use crate::tray;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+// Consecutive crashes before we stop trying
+const MAX_RESTARTS: u32 = 5;
+// A process that stayed up this long resets the crash count
+const STABLE_AFTER: Duration = Duration::from_secs(60);
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum OverlayHealth {
+    Stopped,
+    Running,
+    Restarting { attempt: u32, reason: String },
+    Failed { reason: String },
+}
+
+impl OverlayHealth {
+    pub fn tooltip(&self) -> String {
+        match self {
+            OverlayHealth::Stopped | OverlayHealth::Running => tray::DEFAULT_TOOLTIP.to_owned(),
+            OverlayHealth::Restarting { attempt, reason } => {
+                format!("RedShift: overlay crashed, restarting ({}/{})\n{}", attempt, MAX_RESTARTS, reason)
+            }
+            OverlayHealth::Failed { reason } => format!("RedShift: overlay failed\n{}", reason),
+        }
+    }
+}
+
+struct Supervisor {
+    process: Option<OverlayProcess>,
+    // Config to restart with; None when the overlay is meant to be off
+    config: Option<OverlayConfig>,
+    failures: u32,
+    restart_at: Option<Instant>,
+    health: OverlayHealth,
+    // Paused by the user; a restarted overlay has to start out paused too
+    paused: bool,
+}
+
+impl Supervisor {
+    const fn new() -> Self {
+        Self {
+            process: None,
+            config: None,
+            failures: 0,
+            restart_at: None,
+            health: OverlayHealth::Stopped,
+            paused: false,
+        }
+    }
+
+    fn supervise(&mut self, config: &OverlayConfig) {
+        self.config = Some(config.clone());
+        self.failures = 0;
+        self.restart_at = None;
+    }
+
+    // A restarted overlay gets its settings on the command line, which beat config.json, so
+    // they have to follow every change made since it was started
+    fn refresh(&mut self, config: &OverlayConfig) {
+        if self.config.is_some() {
+            self.config = Some(config.clone());
+        }
+    }
+
+    // What a restart would start the overlay with
+    fn respawn_config(&self) -> Option<OverlayConfig> {
+        self.config.clone()
+    }
+}
+
+static SUPERVISOR: Mutex<Supervisor> = Mutex::new(Supervisor::new());
+
+fn set_health(supervisor: &mut Supervisor, health: OverlayHealth) {
+    if supervisor.health != health {
+        println!("Overlay health: {:?}", health);
+        tray::set_tooltip(&health.tooltip());
+        supervisor.health = health;
+    }
+}
+
+// Start the overlay (if it isn't running) and keep it running from now on
+pub fn start(config: &OverlayConfig) -> io::Result<()> {
+    let mut supervisor = SUPERVISOR.lock().unwrap();
+    supervisor.supervise(config);
+
+    if supervisor.process.is_none() {
+        supervisor.process = Some(OverlayProcess::spawn(config, supervisor.paused)?);
+    }
+    set_health(&mut supervisor, OverlayHealth::Running);
+    Ok(())
+}
+
+// Stop supervising and hand back the process so the caller can shut it down
+pub fn stop() -> Option<OverlayProcess> {
+    let mut supervisor = SUPERVISOR.lock().unwrap();
+    supervisor.config = None;
+    supervisor.restart_at = None;
+    set_health(&mut supervisor, OverlayHealth::Stopped);
+    supervisor.process.take()
+}
+
+// The settings changed; no-op unless the overlay is meant to be running
+pub fn update_config(config: &OverlayConfig) {
+    SUPERVISOR.lock().unwrap().refresh(config);
+}
+
+pub fn health() -> OverlayHealth {
+    SUPERVISOR.lock().unwrap().health.clone()
+}
+
+pub fn send(command: ipc::Command) -> io::Result<u64> {
+    match SUPERVISOR.lock().unwrap().process.as_ref() {
+        Some(process) => process.send(command),
+        None => Err(io::Error::new(io::ErrorKind::NotConnected, "overlay is not running")),
+    }
+}
+
+// Pause or resume the running overlay, and any overlay started later
+pub fn set_paused(paused: bool) -> io::Result<()> {
+    let mut supervisor = SUPERVISOR.lock().unwrap();
+    supervisor.paused = paused;
+    match supervisor.process.as_ref() {
+        Some(process) => process.send(if paused { ipc::Command::Pause } else { ipc::Command::Resume }).map(|_| ()),
+        None => Ok(()),
+    }
+}
+
+pub fn with_process<R>(f: impl FnOnce(Option<&OverlayProcess>) -> R) -> R {
+    f(SUPERVISOR.lock().unwrap().process.as_ref())
+}
+
+fn backoff(failures: u32) -> Duration {
+    let factor = 1u32 << failures.saturating_sub(1).min(5);
+    (FIRST_BACKOFF * factor).min(MAX_BACKOFF)
+}
+
+pub fn spawn_watchdog() {
+    thread::spawn(|| loop {
+        thread::sleep(POLL_INTERVAL);
+        check();
+    });
+}
+
+fn check() {
+    let exited = {
+        let mut supervisor = SUPERVISOR.lock().unwrap();
+        match supervisor.process.as_mut().map(|process| (process.pid(), process.try_wait())) {
+            Some((pid, Ok(Some(status)))) => Some((pid, status)),
+            Some((_, Ok(None))) => return,
+            Some((_, Err(e))) => {
+                eprintln!("Failed to check overlay process: {}", e);
+                return;
+            }
+            None => None,
+        }
+    };
+
+    if let Some((pid, status)) = exited {
+        if !status.success() {
+            // Give the stderr reader a moment to catch the last lines before reading them. The
+            // settings window polls the supervisor every frame, so don't hold the lock meanwhile.
+            thread::sleep(Duration::from_millis(100));
+        }
+        collect(pid, status);
+        return;
+    }
+
+    let mut supervisor = SUPERVISOR.lock().unwrap();
+    let supervisor = &mut *supervisor;
+
+    let due = matches!(supervisor.restart_at, Some(at) if Instant::now() >= at);
+    if !due {
+        return;
+    }
+    supervisor.restart_at = None;
+
+    let Some(config) = supervisor.respawn_config() else { return };
+    match OverlayProcess::spawn(&config, supervisor.paused) {
+        Ok(process) => {
+            supervisor.process = Some(process);
+            set_health(supervisor, OverlayHealth::Running);
+        }
+        Err(e) => {
+            supervisor.failures += 1;
+            let reason = format!("Failed to start overlay: {}", e);
+            if supervisor.failures > MAX_RESTARTS {
+                set_health(supervisor, OverlayHealth::Failed { reason });
+            } else {
+                supervisor.restart_at = Some(Instant::now() + backoff(supervisor.failures));
+                let attempt = supervisor.failures;
+                set_health(supervisor, OverlayHealth::Restarting { attempt, reason });
+            }
+        }
+    }
+}
+
+// Forget an overlay that exited. It exits cleanly only after fading out on its own (Ctrl+C, a
+// lost controller connection, `enabled: false` in the file), which isn't worth a restart.
+fn collect(pid: u32, status: ExitStatus) {
+    let mut supervisor = SUPERVISOR.lock().unwrap();
+    let supervisor = &mut *supervisor;
+    // Stopped or replaced while we weren't looking
+    let Some(process) = supervisor.process.as_ref().filter(|process| process.pid() == pid) else { return };
+
+    if status.success() {
+        println!("Overlay process exited by itself");
+        supervisor.process = None;
+        supervisor.config = None;
+        supervisor.failures = 0;
+        supervisor.restart_at = None;
+        set_health(supervisor, OverlayHealth::Stopped);
+        return;
+    }
+
+    let reason = process.failure_reason(status);
+    if process.uptime() >= STABLE_AFTER {
+        supervisor.failures = 0;
+    }
+    supervisor.process = None;
+    supervisor.failures += 1;
+    eprintln!("Overlay process died: {}", reason);
+
+    if supervisor.failures > MAX_RESTARTS {
+        supervisor.restart_at = None;
+        set_health(supervisor, OverlayHealth::Failed { reason });
+    } else {
+        let delay = backoff(supervisor.failures);
+        println!("Restarting overlay in {:?}", delay);
+        supervisor.restart_at = Some(Instant::now() + delay);
+        let attempt = supervisor.failures;
+        set_health(supervisor, OverlayHealth::Restarting { attempt, reason });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::overlay_process;
+
+    #[test]
+    fn restart_uses_settings_changed_after_start() {
+        let mut supervisor = Supervisor::new();
+        let mut config = OverlayConfig::default();
+        supervisor.supervise(&config);
+
+        config.opacity = 180;
+        config.color_temperature = 2400;
+        config.tint_color = [255, 128, 0];
+        config.brightness = 60;
+        supervisor.refresh(&config);
+
+        let args = overlay_process::config_args(&supervisor.respawn_config().unwrap(), supervisor.paused);
+        assert_eq!(args, overlay_process::config_args(&config, false));
+        for expected in [["--opacity", "180"], ["--temperature", "2400"], ["--color", "#ff8000"], ["--brightness", "60"]] {
+            assert!(args.windows(2).any(|pair| pair == expected), "missing {:?} in {:?}", expected, args);
+        }
+    }
+
+    #[test]
+    fn changes_while_stopped_dont_restart_anything() {
+        let mut supervisor = Supervisor::new();
+        supervisor.refresh(&OverlayConfig::default());
+        assert!(supervisor.respawn_config().is_none());
+    }
+}
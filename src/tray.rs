@@ -0,0 +1,78 @@
+// Tray tooltip updates from any thread.
+//
+// systray's Application lives on the tray thread, which is parked in wait_for_message(), so its
+// set_tooltip can't be reached from the rest of the controller. Instead we look up the hidden
+// window systray registers the icon with (window class "my_window", owned by this process, icon
+// id 1) and modify the icon's tooltip through the shell directly.
+
+#[cfg(target_os = "windows")]
+use std::mem;
+#[cfg(target_os = "windows")]
+use winapi::{
+    shared::minwindef::{BOOL, DWORD, FALSE, LPARAM, TRUE},
+    shared::windef::HWND,
+    um::processthreadsapi::GetCurrentProcessId,
+    um::shellapi::{Shell_NotifyIconW, NIF_TIP, NIM_MODIFY, NOTIFYICONDATAW},
+    um::winuser::{EnumWindows, GetClassNameW, GetWindowThreadProcessId},
+};
+
+#[cfg(target_os = "windows")]
+const TRAY_WINDOW_CLASS: &str = "my_window";
+#[cfg(target_os = "windows")]
+const TRAY_ICON_ID: u32 = 1;
+
+pub const DEFAULT_TOOLTIP: &str = "RedShift Controller";
+
+// There is no tray icon outside Windows yet
+#[cfg(not(target_os = "windows"))]
+pub fn set_tooltip(_text: &str) {}
+
+#[cfg(target_os = "windows")]
+pub fn set_tooltip(text: &str) {
+    let Some(hwnd) = find_tray_window() else {
+        eprintln!("Failed to update tray tooltip: tray window not found");
+        return;
+    };
+
+    unsafe {
+        let mut nid: NOTIFYICONDATAW = mem::zeroed();
+        nid.cbSize = mem::size_of::<NOTIFYICONDATAW>() as DWORD;
+        nid.hWnd = hwnd;
+        nid.uID = TRAY_ICON_ID;
+        nid.uFlags = NIF_TIP;
+
+        // Leave room for the terminating zero
+        let tip: Vec<u16> = text.encode_utf16().take(nid.szTip.len() - 1).collect();
+        nid.szTip[..tip.len()].copy_from_slice(&tip);
+
+        if Shell_NotifyIconW(NIM_MODIFY, &mut nid) == 0 {
+            eprintln!("Failed to update tray tooltip");
+        }
+    }
+}
+
+#[cfg(target_os = "windows")]
+fn find_tray_window() -> Option<HWND> {
+    let mut found: HWND = std::ptr::null_mut();
+    unsafe {
+        EnumWindows(Some(match_tray_window), &mut found as *mut HWND as LPARAM);
+    }
+    (!found.is_null()).then_some(found)
+}
+
+#[cfg(target_os = "windows")]
+unsafe extern "system" fn match_tray_window(hwnd: HWND, lparam: LPARAM) -> BOOL {
+    let mut pid: DWORD = 0;
+    GetWindowThreadProcessId(hwnd, &mut pid);
+    if pid != GetCurrentProcessId() {
+        return TRUE;
+    }
+
+    let mut class = [0u16; 64];
+    let len = GetClassNameW(hwnd, class.as_mut_ptr(), class.len() as i32);
+    if len > 0 && String::from_utf16_lossy(&class[..len as usize]) == TRAY_WINDOW_CLASS {
+        *(lparam as *mut HWND) = hwnd;
+        return FALSE;
+    }
+    TRUE
+}